@@ -0,0 +1,183 @@
+use specs::prelude::*;
+use crate::game_map::GameMap;
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::components::Viewshed;
+use super::RunState;
+
+/// Each player turn takes a minute.
+pub const TURNS_PER_HOUR: u32 = 60;
+const HOURS_PER_DAY: u32 = 24;
+/// The player rides into town in the morning.
+const STARTING_HOUR: u32 = 8;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TimeOfDay {
+    Day,
+    Dusk,
+    Night
+}
+
+/// The world clock, counted in turns since midnight on the first day.
+pub struct GameClock {
+    pub turn: u32
+}
+
+impl Default for GameClock {
+    fn default() -> GameClock {
+        GameClock { turn: STARTING_HOUR * TURNS_PER_HOUR }
+    }
+}
+
+impl GameClock {
+    pub fn hour(&self) -> u32 {
+        (self.turn / TURNS_PER_HOUR) % HOURS_PER_DAY
+    }
+
+    pub fn minute(&self) -> u32 {
+        self.turn % TURNS_PER_HOUR
+    }
+
+    pub fn time_of_day(&self) -> TimeOfDay {
+        match self.hour() {
+            6..=17 => TimeOfDay::Day,
+            18..=20 => TimeOfDay::Dusk,
+            _ => TimeOfDay::Night
+        }
+    }
+
+    /// How much the sky lights up open ground. Night is too dark to see by without a lamp.
+    pub fn ambient_light(&self) -> f32 {
+        match self.time_of_day() {
+            TimeOfDay::Day => 1.0,
+            TimeOfDay::Dusk => 0.45,
+            TimeOfDay::Night => 0.05
+        }
+    }
+
+    /// How far something that sees `range` tiles by day can see out in the open now.
+    pub fn sight_range(&self, range: i32) -> i32 {
+        match self.time_of_day() {
+            TimeOfDay::Day => range,
+            TimeOfDay::Dusk => range * 3 / 4,
+            TimeOfDay::Night => range / 2
+        }
+    }
+
+    pub fn is_on_the_hour(&self) -> bool {
+        self.minute() == 0
+    }
+
+    /// The time as shown in the HUD, e.g. "18:05 Dusk".
+    pub fn describe(&self) -> String {
+        format!("{:02}:{:02} {:?}", self.hour(), self.minute(), self.time_of_day())
+    }
+}
+
+/// Moves the clock on a minute every player turn. On outdoor maps the sky sets the
+/// ambient light, and everyone looks again when the sun comes up or goes down.
+pub struct ClockSystem;
+
+impl<'a> System<'a> for ClockSystem {
+    type SystemData = ( WriteExpect<'a, GameClock>,
+                        WriteExpect<'a, GameMap>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, GameLog>,
+                        WriteStorage<'a, Viewshed> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut clock, mut map, run_state, mut log, mut viewsheds) = data;
+
+        let before = clock.time_of_day();
+        if *run_state == RunState::PlayerTurn {
+            clock.turn += 1;
+        }
+        if !map.outdoors {
+            return;
+        }
+
+        map.ambient_light = clock.ambient_light();
+        let now = clock.time_of_day();
+        if now != before {
+            let news = match now {
+                TimeOfDay::Day => "The sun comes up over the hills.",
+                TimeOfDay::Dusk => "The sun sinks low and the shadows stretch out.",
+                TimeOfDay::Night => "Night falls. Somewhere out in the desert, coyotes howl."
+            };
+            LogBuilder::new(LogCategory::General).text(news).major().log(&mut log);
+            for viewshed in (&mut viewsheds).join() {
+                viewshed.dirty = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spawner;
+    use crate::components::{Name, Monster, Position};
+    use crate::snapshot::fixture_world;
+
+    #[test]
+    fn the_day_is_split_into_day_dusk_and_night() {
+        let at = |hour: u32, minute: u32| GameClock { turn: hour * TURNS_PER_HOUR + minute };
+        assert_eq!(GameClock::default().describe(), "08:00 Day");
+        assert_eq!(at(17, 59).time_of_day(), TimeOfDay::Day);
+        assert_eq!(at(18, 0).time_of_day(), TimeOfDay::Dusk);
+        assert_eq!(at(21, 0).time_of_day(), TimeOfDay::Night);
+        assert_eq!(at(29, 30).describe(), "05:30 Night");
+        assert_eq!(at(30, 0).time_of_day(), TimeOfDay::Day);
+        assert_eq!(at(12, 0).sight_range(8), 8);
+        assert_eq!(at(19, 0).sight_range(8), 6);
+        assert_eq!(at(23, 0).sight_range(8), 4);
+    }
+
+    #[test]
+    fn only_player_turns_move_the_clock_and_the_sky_lights_the_surface() {
+        let mut ecs = fixture_world();
+        ecs.insert(GameClock { turn: 18 * TURNS_PER_HOUR - 1 });
+        ecs.fetch_mut::<GameMap>().outdoors = true;
+
+        ecs.insert(RunState::MonsterTurn);
+        ClockSystem.run_now(&ecs);
+        assert_eq!(ecs.fetch::<GameClock>().time_of_day(), TimeOfDay::Day);
+        assert_eq!(ecs.fetch::<GameMap>().ambient_light, 1.0);
+        for viewshed in (&mut ecs.write_storage::<Viewshed>()).join() {
+            viewshed.dirty = false;
+        }
+
+        ecs.insert(RunState::PlayerTurn);
+        ClockSystem.run_now(&ecs);
+        assert_eq!(ecs.fetch::<GameClock>().time_of_day(), TimeOfDay::Dusk);
+        assert_eq!(ecs.fetch::<GameMap>().ambient_light, 0.45);
+        assert!(ecs.read_storage::<Viewshed>().join().all(|viewshed| viewshed.dirty));
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].fragments[0].text, "The sun sinks low and the shadows stretch out.");
+    }
+
+    #[test]
+    fn who_turns_up_on_the_street_depends_on_the_hour() {
+        let wanderers = |hour: u32| {
+            let mut ecs = fixture_world();
+            ecs.insert(GameClock { turn: hour * TURNS_PER_HOUR });
+            let ground: Vec<usize> = (22..=30).map(|x| ecs.fetch::<GameMap>().xy_idx(x, 10)).collect();
+            for _ in 0..10 {
+                spawner::spawn_wanderer(&mut ecs, &ground);
+            }
+            let names = ecs.read_storage::<Name>();
+            let positions = ecs.read_storage::<Position>();
+            let monsters = ecs.read_storage::<Monster>();
+            let found: Vec<(String, bool)> = (&ecs.entities(), &names, &positions).join()
+                .filter(|(_, _, pos)| pos.x >= 22)
+                .map(|(entity, name, _)| (name.name.clone(), monsters.contains(entity)))
+                .collect();
+            found
+        };
+
+        let day = wanderers(12);
+        assert_eq!(day.len(), 10);
+        assert!(day.iter().all(|(name, hostile)| (name == "Drifter" || name == "Cowhand") && !hostile));
+        let night = wanderers(2);
+        assert_eq!(night.len(), 10);
+        assert!(night.iter().all(|(name, hostile)| (name == "Coyote" || name == "Outlaw") && *hostile));
+    }
+}
@@ -1,7 +1,7 @@
 use specs::prelude::*;
 use rltk::{Point, RGB};
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone)]
 pub struct Position {
     pub x: i32,
     pub y: i32
@@ -11,7 +11,9 @@ pub struct Position {
 pub struct Renderable {
     pub glyph: u8,
     pub fg: RGB,
-    pub bg: RGB
+    pub bg: RGB,
+    /// When several things share a tile, the lowest order is drawn on top.
+    pub render_order: i32
 }
 
 #[derive(Component)]
@@ -53,6 +55,17 @@ pub struct SufferDamage {
     pub amount: i32
 }
 
+impl SufferDamage {
+    /// Adds to any damage the victim has already taken this turn.
+    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+        if let Some(suffering) = store.get_mut(victim) {
+            suffering.amount += amount;
+        } else {
+            store.insert(victim, SufferDamage { amount }).expect("Unable to insert damage");
+        }
+    }
+}
+
 #[derive(Component, Debug)]
 pub struct Item;
 
@@ -81,3 +94,102 @@ pub struct WantsToDropItem {
 pub struct WantsToDrinkPotion {
     pub potion: Entity
 }
+
+/// Heals a hit point every `turns_per_hp` turns, as long as no monster is in view.
+#[derive(Component, Debug)]
+pub struct HealthRegen {
+    pub turns_per_hp: i32,
+    pub turns_left: i32
+}
+
+/// Blocks line of sight, the way `BlocksTile` blocks movement.
+#[derive(Component, Debug)]
+pub struct BlocksVisibility;
+
+/// Closed doors also block movement and sight.
+#[derive(Component, Debug)]
+pub struct Door {
+    pub open: bool
+}
+
+/// A locked door opens for a `Key` with the same `lock`, or for a lockpicking roll
+/// of at least `difficulty` on a d20.
+#[derive(Component, Debug)]
+pub struct Locked {
+    pub lock: u32,
+    pub difficulty: i32
+}
+
+#[derive(Component, Debug)]
+pub struct Key {
+    pub lock: u32
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToOpenDoor {
+    pub door: Entity
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct WantsToCloseDoor {
+    pub door: Entity
+}
+
+/// Not drawn or described until the player has spotted it.
+#[derive(Component, Debug)]
+pub struct Hidden;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    BearTrap,
+    TripwireShotgun,
+    SnakePit
+}
+
+/// Springs on whatever steps onto its tile.
+#[derive(Component, Debug)]
+pub struct Trap {
+    pub kind: TrapKind
+}
+
+/// Marks anything that changed tile this turn, so traps can tell what stepped on them.
+#[derive(Component, Debug)]
+pub struct EntityMoved;
+
+/// Can't move for `turns` more turns.
+#[derive(Component, Debug)]
+pub struct Immobilized {
+    pub turns: i32
+}
+
+/// Loses a hit point every turn for `turns` more turns.
+#[derive(Component, Debug)]
+pub struct Poisoned {
+    pub turns: i32
+}
+
+/// Lights up the tiles it can see, brightest close by. A light in a backpack shines
+/// from wherever its owner is standing.
+#[derive(Component, Debug)]
+pub struct LightSource {
+    pub range: i32,
+    pub intensity: f32
+}
+
+/// Turns of light left in a lantern. Only a carried lantern burns, one at a time.
+#[derive(Component, Debug)]
+pub struct Fuel {
+    pub turns: i32
+}
+
+/// A monster out in the open that has lost sight of the player, following their
+/// tracks to where they were last seen for `turns_left` more turns.
+#[derive(Component, Debug)]
+pub struct Tracking {
+    pub last_seen: Point,
+    pub turns_left: i32
+}
+
+/// Someone who turned up on the street by themselves, rather than with the level.
+#[derive(Component, Debug)]
+pub struct Wanderer;
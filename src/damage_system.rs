@@ -1,6 +1,6 @@
 use specs::prelude::*;
-use crate::components::{CombatStats, SufferDamage, Player, Name};
-use crate::game_log::GameLog;
+use crate::components::{CombatStats, SufferDamage, Player, Name, Renderable};
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
 
 pub struct DamageSystem;
 
@@ -11,7 +11,7 @@ impl<'a> System<'a> for DamageSystem {
     fn run(&mut self, data: Self::SystemData) {
         let (mut stats, mut damage) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
+        for (stats, damage) in (&mut stats, &damage).join() {
             stats.hp -= damage.amount;
         }
 
@@ -27,16 +27,24 @@ pub fn delete_the_dead(ecs: &mut World) {
         let combat_stats = ecs.read_storage::<CombatStats>();
         let players = ecs.read_storage::<Player>();
         let names = ecs.read_storage::<Name>();
+        let renderables = ecs.read_storage::<Renderable>();
 
         for (entity, name, stats) in (&entities, &names, &combat_stats).join() {
             if stats.hp < 1 {
                 match players.get(entity) {
                     None => {
-                        log.entries.insert(0, format!("{} is dead", &name.name));
+                        LogBuilder::new(LogCategory::Combat)
+                            .name(&name.name, renderables.get(entity))
+                            .text(" is dead")
+                            .major()
+                            .log(&mut log);
                         dead.push(entity);
                     },
                     Some(_) => {
-                        log.entries.insert(0, "You are dead!".to_string());
+                        LogBuilder::new(LogCategory::Combat)
+                            .colored("You are dead!", rltk::RGB::named(rltk::RED))
+                            .major()
+                            .log(&mut log);
                     }
                 }
             }
@@ -0,0 +1,236 @@
+use specs::prelude::*;
+use rltk::RandomNumberGenerator;
+use crate::game_map::GameMap;
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::components::{
+    Door, Locked, Key, InBackpack, Name, Position, Renderable, Viewshed,
+    BlocksTile, BlocksVisibility, WantsToOpenDoor, WantsToCloseDoor
+};
+
+pub const CLOSED_DOOR_GLYPH: char = '+';
+pub const OPEN_DOOR_GLYPH: char = '/';
+
+/// Opens and closes the doors that anyone has walked into or asked to shut. A locked
+/// door needs the right key from the opener's backpack; the player can also try to
+/// pick the lock, which takes the turn whether it works or not.
+pub struct DoorSystem;
+
+impl<'a> System<'a> for DoorSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( Entities<'a>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, GameMap>,
+                        WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Key>,
+                        ReadStorage<'a, InBackpack>,
+                        WriteStorage<'a, WantsToOpenDoor>,
+                        WriteStorage<'a, WantsToCloseDoor>,
+                        WriteStorage<'a, Door>,
+                        WriteStorage<'a, Locked>,
+                        WriteStorage<'a, Renderable>,
+                        WriteStorage<'a, BlocksTile>,
+                        WriteStorage<'a, BlocksVisibility>,
+                        WriteStorage<'a, Viewshed> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            map,
+            mut gamelog,
+            mut rng,
+            names,
+            positions,
+            keys,
+            backpack,
+            mut wants_open,
+            mut wants_close,
+            mut doors,
+            mut locks,
+            mut renderables,
+            mut blockers,
+            mut view_blockers,
+            mut viewsheds
+        ) = data;
+
+        let mut changed = false;
+
+        let openers: Vec<(Entity, Entity)> = (&entities, &wants_open).join().map(|(opener, wants)| (opener, wants.door)).collect();
+        wants_open.clear();
+        for (opener, door_entity) in openers {
+            let is_player = opener == *player_entity;
+            if let Some(lock) = locks.get(door_entity) {
+                let key = (&entities, &keys, &backpack).join()
+                    .find(|(_, key, pack)| pack.owner == opener && key.lock == lock.lock)
+                    .map(|(key, _, _)| key);
+                match key {
+                    Some(key) if is_player => {
+                        let key_name = names.get(key).map_or("key", |name| name.name.as_str());
+                        LogBuilder::new(LogCategory::General).text(format!("You unlock the door with the {}.", key_name)).log(&mut gamelog);
+                    },
+                    Some(_) => {},
+                    None if is_player => {
+                        if rng.roll_dice(1, 20) < lock.difficulty {
+                            LogBuilder::new(LogCategory::General).text("You fail to pick the lock.").log(&mut gamelog);
+                            continue;
+                        }
+                        LogBuilder::new(LogCategory::General).text("You pick the lock.").log(&mut gamelog);
+                    },
+                    None => continue
+                }
+                locks.remove(door_entity);
+            }
+
+            if let Some(door) = doors.get_mut(door_entity) {
+                door.open = true;
+                blockers.remove(door_entity);
+                view_blockers.remove(door_entity);
+                if let Some(render) = renderables.get_mut(door_entity) {
+                    render.glyph = rltk::to_cp437(OPEN_DOOR_GLYPH);
+                }
+                changed = true;
+
+                if is_player {
+                    LogBuilder::new(LogCategory::General).text("You open the door.").minor().log(&mut gamelog);
+                } else if positions.get(door_entity).is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]) {
+                    let opener_name = names.get(opener).map_or("Something", |name| name.name.as_str());
+                    LogBuilder::new(LogCategory::General)
+                        .name(opener_name, renderables.get(opener))
+                        .text(" opens a door.")
+                        .log(&mut gamelog);
+                }
+            }
+        }
+
+        for (closer, wants) in (&entities, &wants_close).join() {
+            if let Some(door) = doors.get_mut(wants.door) {
+                door.open = false;
+                blockers.insert(wants.door, BlocksTile).expect("Unable to block the door");
+                view_blockers.insert(wants.door, BlocksVisibility).expect("Unable to block sight through the door");
+                if let Some(render) = renderables.get_mut(wants.door) {
+                    render.glyph = rltk::to_cp437(CLOSED_DOOR_GLYPH);
+                }
+                changed = true;
+
+                if closer == *player_entity {
+                    LogBuilder::new(LogCategory::General).text("You close the door.").minor().log(&mut gamelog);
+                }
+            }
+        }
+        wants_close.clear();
+
+        // what everyone can see has changed
+        if changed {
+            for viewshed in (&mut viewsheds).join() {
+                viewshed.dirty = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rltk::Point;
+    use crate::{RunState, spawner, try_move_player};
+    use crate::components::Monster;
+    use crate::map_ai_system::MapIndexingSystem;
+    use crate::monster_ai_system::MonsterAI;
+    use crate::snapshot::{fixture_world, named};
+
+    fn door_at(ecs: &World, x: i32, y: i32) -> Entity {
+        let map = ecs.fetch::<GameMap>();
+        let doors = ecs.read_storage::<Door>();
+        *map.tile_content[map.xy_idx(x, y)].iter().find(|entity| doors.contains(**entity)).expect("no door there")
+    }
+
+    fn newest_log(ecs: &World) -> String {
+        ecs.fetch::<GameLog>().entries[0].fragments.iter().map(|fragment| fragment.text.as_str()).collect()
+    }
+
+    #[test]
+    fn bumping_opens_a_door_and_closing_it_blocks_sight_again() {
+        let mut ecs = fixture_world();
+        spawner::door(&mut ecs, 21, 10, None);
+        MapIndexingSystem.run_now(&ecs);
+        {
+            let map = ecs.fetch::<GameMap>();
+            let idx = map.xy_idx(21, 10);
+            assert!(map.blocked[idx] && map.view_blocked[idx] && map.openable_doors[idx]);
+        }
+
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<Position>().insert(player, Position { x: 20, y: 10 }).unwrap();
+        try_move_player(&mut ecs, 1, 0);
+        assert_eq!(ecs.read_storage::<Position>().get(player).unwrap().x, 20, "bumping doesn't move");
+
+        DoorSystem.run_now(&ecs);
+        MapIndexingSystem.run_now(&ecs);
+        let door = door_at(&ecs, 21, 10);
+        assert!(ecs.read_storage::<Door>().get(door).unwrap().open);
+        {
+            let map = ecs.fetch::<GameMap>();
+            let idx = map.xy_idx(21, 10);
+            assert!(!map.blocked[idx] && !map.view_blocked[idx] && !map.openable_doors[idx]);
+        }
+
+        ecs.write_storage::<WantsToCloseDoor>().insert(player, WantsToCloseDoor { door }).unwrap();
+        DoorSystem.run_now(&ecs);
+        MapIndexingSystem.run_now(&ecs);
+        assert!(!ecs.read_storage::<Door>().get(door).unwrap().open);
+        assert!(ecs.fetch::<GameMap>().view_blocked[ecs.fetch::<GameMap>().xy_idx(21, 10)]);
+    }
+
+    #[test]
+    fn locked_doors_need_the_key_or_a_picked_lock() {
+        let mut ecs = fixture_world();
+        // too hard to pick
+        spawner::door(&mut ecs, 21, 10, Some(Locked { lock: 7, difficulty: 21 }));
+        MapIndexingSystem.run_now(&ecs);
+        assert!(!ecs.fetch::<GameMap>().openable_doors[ecs.fetch::<GameMap>().xy_idx(21, 10)]);
+
+        let player = *ecs.fetch::<Entity>();
+        let door = door_at(&ecs, 21, 10);
+        ecs.write_storage::<WantsToOpenDoor>().insert(player, WantsToOpenDoor { door }).unwrap();
+        DoorSystem.run_now(&ecs);
+        assert_eq!(newest_log(&ecs), "You fail to pick the lock.");
+        assert!(ecs.read_storage::<Locked>().contains(door));
+
+        ecs.create_entity()
+            .with(Name { name: "Cellar Key".to_string() })
+            .with(Key { lock: 7 })
+            .with(InBackpack { owner: player })
+            .build();
+        ecs.write_storage::<WantsToOpenDoor>().insert(player, WantsToOpenDoor { door }).unwrap();
+        DoorSystem.run_now(&ecs);
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].fragments[0].text, "You unlock the door with the Cellar Key.");
+        assert!(!ecs.read_storage::<Locked>().contains(door));
+        assert!(ecs.read_storage::<Door>().get(door).unwrap().open);
+    }
+
+    #[test]
+    fn monsters_open_doors_in_their_way() {
+        let mut ecs = fixture_world();
+        spawner::door(&mut ecs, 21, 10, None);
+        let orc = named(&ecs, "Orc");
+        ecs.write_storage::<Position>().insert(orc, Position { x: 22, y: 10 }).unwrap();
+        // the orc caught sight of the player before the door was shut
+        ecs.write_storage::<Viewshed>().get_mut(orc).unwrap().visible_tiles = vec![Point::new(10, 10)];
+        ecs.insert(RunState::MonsterTurn);
+        MapIndexingSystem.run_now(&ecs);
+
+        MonsterAI.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Position>().get(orc).unwrap().x, 22);
+        assert!(ecs.read_storage::<WantsToOpenDoor>().contains(orc));
+        assert!(ecs.read_storage::<Monster>().contains(orc));
+
+        DoorSystem.run_now(&ecs);
+        MapIndexingSystem.run_now(&ecs);
+        MonsterAI.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Position>().get(orc).unwrap().x, 21);
+        assert_eq!(newest_log(&ecs), "Orc opens a door.");
+    }
+}
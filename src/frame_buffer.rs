@@ -0,0 +1,93 @@
+use rltk::{RGB, VirtualKeyCode};
+use super::frontend::Frontend;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub struct Cell {
+    pub glyph: u8,
+    pub fg: RGB,
+    pub bg: RGB
+}
+
+const BLANK: Cell = Cell {
+    glyph: 32,
+    fg: RGB { r: 1.0, g: 1.0, b: 1.0 },
+    bg: RGB { r: 0.0, g: 0.0, b: 0.0 }
+};
+
+/// A frame kept in memory instead of on a window. Whoever owns it fills in the
+/// input for the frame and reads the cells back out afterwards.
+pub struct FrameBuffer {
+    pub width: i32,
+    pub height: i32,
+    pub cells: Vec<Cell>,
+    /// The tile layer underneath the glyphs: a sprite and its tint.
+    pub sprites: Vec<Option<(u8, RGB)>>,
+    pub key: Option<VirtualKeyCode>,
+    pub shift: bool,
+    pub mouse_pos: (i32, i32),
+    pub left_click: bool,
+    pub frame_time_ms: f32
+}
+
+impl FrameBuffer {
+    pub fn new(width: i32, height: i32) -> FrameBuffer {
+        FrameBuffer {
+            width,
+            height,
+            cells: vec![BLANK; (width * height) as usize],
+            sprites: vec![None; (width * height) as usize],
+            key: None,
+            shift: false,
+            mouse_pos: (0, 0),
+            left_click: false,
+            frame_time_ms: 0.0
+        }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Cell {
+        self.cells[(y * self.width + x) as usize]
+    }
+}
+
+impl Frontend for FrameBuffer {
+    fn cls(&mut self) {
+        for cell in self.cells.iter_mut() {
+            *cell = BLANK;
+        }
+        for sprite in self.sprites.iter_mut() {
+            *sprite = None;
+        }
+    }
+
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: u8) {
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            self.cells[(y * self.width + x) as usize] = Cell { glyph, fg, bg };
+        }
+    }
+
+    fn set_sprite(&mut self, x: i32, y: i32, tint: RGB, sprite: u8) {
+        if x >= 0 && x < self.width && y >= 0 && y < self.height {
+            self.sprites[(y * self.width + x) as usize] = Some((sprite, tint));
+        }
+    }
+
+    fn key(&self) -> Option<VirtualKeyCode> {
+        self.key
+    }
+
+    fn shift(&self) -> bool {
+        self.shift
+    }
+
+    fn mouse_pos(&self) -> (i32, i32) {
+        self.mouse_pos
+    }
+
+    fn left_click(&self) -> bool {
+        self.left_click
+    }
+
+    fn frame_time_ms(&self) -> f32 {
+        self.frame_time_ms
+    }
+}
@@ -0,0 +1,212 @@
+use rltk::{Console, Rltk, RGB, VirtualKeyCode, to_cp437, string_to_cp437};
+
+/// Everything the game needs from whatever is showing it: a grid of glyphs to draw
+/// into, and the input that arrived since the last frame.
+pub trait Frontend {
+    fn cls(&mut self);
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: u8);
+
+    /// Draws a tileset sprite underneath the glyphs, tinted by `tint`. Only frontends
+    /// with a tile layer are ever handed a tileset with sprites in it, so the rest can
+    /// ignore this.
+    fn set_sprite(&mut self, _x: i32, _y: i32, _tint: RGB, _sprite: u8) {}
+
+    fn key(&self) -> Option<VirtualKeyCode>;
+    fn shift(&self) -> bool;
+    fn mouse_pos(&self) -> (i32, i32);
+    fn left_click(&self) -> bool;
+    fn frame_time_ms(&self) -> f32;
+
+    /// Prints white text on black.
+    fn print(&mut self, x: i32, y: i32, text: &str) {
+        self.print_color(x, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), text);
+    }
+
+    fn print_color(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, text: &str) {
+        for (i, glyph) in string_to_cp437(text).into_iter().enumerate() {
+            self.set(x + i as i32, y, fg, bg, glyph);
+        }
+    }
+
+    /// A cleared box with a single line border, the same as rltk draws it.
+    fn draw_box(&mut self, x: i32, y: i32, width: i32, height: i32, fg: RGB, bg: RGB) {
+        for by in y..y + height {
+            for bx in x..x + width {
+                self.set(bx, by, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), 32);
+            }
+        }
+
+        self.set(x, y, fg, bg, to_cp437('┌'));
+        self.set(x + width, y, fg, bg, to_cp437('┐'));
+        self.set(x, y + height, fg, bg, to_cp437('└'));
+        self.set(x + width, y + height, fg, bg, to_cp437('┘'));
+        for bx in x + 1..x + width {
+            self.set(bx, y, fg, bg, to_cp437('─'));
+            self.set(bx, y + height, fg, bg, to_cp437('─'));
+        }
+        for by in y + 1..y + height {
+            self.set(x, by, fg, bg, to_cp437('│'));
+            self.set(x + width, by, fg, bg, to_cp437('│'));
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw_bar_horizontal(&mut self, x: i32, y: i32, width: i32, n: i32, max: i32, fg: RGB, bg: RGB) {
+        let fill_width = (n as f32 / max as f32 * width as f32) as i32;
+        for i in 0..width {
+            let glyph = if i <= fill_width { to_cp437('▓') } else { to_cp437('░') };
+            self.set(x + i, y, fg, bg, glyph);
+        }
+    }
+}
+
+/// In tileset mode the sprites go on the first console and the glyphs on the second.
+const TILE_CONSOLE: usize = 0;
+const GLYPH_CONSOLE: usize = 1;
+
+/// The stack of consoles tileset mode draws on, split out from `Rltk` so the console
+/// switching can be checked without opening a window.
+trait Layers {
+    fn layer_count(&self) -> usize;
+    fn select_layer(&mut self, layer: usize);
+    fn clear_layers(&mut self);
+    fn set_on_layer(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: u8);
+}
+
+impl Layers for Rltk {
+    fn layer_count(&self) -> usize {
+        self.consoles.len()
+    }
+
+    fn select_layer(&mut self, layer: usize) {
+        self.set_active_console(layer);
+    }
+
+    fn clear_layers(&mut self) {
+        for console in self.consoles.iter_mut() {
+            console.console.cls();
+        }
+    }
+
+    fn set_on_layer(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: u8) {
+        Console::set(self, x, y, fg, bg, glyph);
+    }
+}
+
+/// Clears every console, and makes sure glyphs go on top: rltk starts out drawing on
+/// the first console, which is the sprites' in tileset mode.
+fn clear_frame<L: Layers>(ctx: &mut L) {
+    ctx.clear_layers();
+    if ctx.layer_count() > GLYPH_CONSOLE {
+        ctx.select_layer(GLYPH_CONSOLE);
+    }
+}
+
+fn draw_sprite<L: Layers>(ctx: &mut L, x: i32, y: i32, tint: RGB, sprite: u8) {
+    if ctx.layer_count() > GLYPH_CONSOLE {
+        ctx.select_layer(TILE_CONSOLE);
+        ctx.set_on_layer(x, y, tint, RGB::named(rltk::BLACK), sprite);
+        ctx.select_layer(GLYPH_CONSOLE);
+    }
+}
+
+impl Frontend for Rltk {
+    fn cls(&mut self) {
+        clear_frame(self);
+    }
+
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: u8) {
+        Console::set(self, x, y, fg, bg, glyph);
+    }
+
+    fn set_sprite(&mut self, x: i32, y: i32, tint: RGB, sprite: u8) {
+        draw_sprite(self, x, y, tint, sprite);
+    }
+
+    fn key(&self) -> Option<VirtualKeyCode> {
+        self.key
+    }
+
+    fn shift(&self) -> bool {
+        self.shift
+    }
+
+    fn mouse_pos(&self) -> (i32, i32) {
+        self.mouse_pos
+    }
+
+    fn left_click(&self) -> bool {
+        self.left_click
+    }
+
+    fn frame_time_ms(&self) -> f32 {
+        self.frame_time_ms
+    }
+
+    fn print(&mut self, x: i32, y: i32, text: &str) {
+        Console::print(self, x, y, text);
+    }
+
+    fn print_color(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, text: &str) {
+        Console::print_color(self, x, y, fg, bg, text);
+    }
+
+    fn draw_box(&mut self, x: i32, y: i32, width: i32, height: i32, fg: RGB, bg: RGB) {
+        Console::draw_box(self, x, y, width, height, fg, bg);
+    }
+
+    fn draw_bar_horizontal(&mut self, x: i32, y: i32, width: i32, n: i32, max: i32, fg: RGB, bg: RGB) {
+        Console::draw_bar_horizontal(self, x, y, width, n, max, fg, bg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records what lands on each console, the way `Rltk` routes `set` to the active one.
+    struct FakeLayers {
+        consoles: Vec<Vec<u8>>,
+        active: usize
+    }
+
+    impl Layers for FakeLayers {
+        fn layer_count(&self) -> usize {
+            self.consoles.len()
+        }
+
+        fn select_layer(&mut self, layer: usize) {
+            self.active = layer;
+        }
+
+        fn clear_layers(&mut self) {
+            for console in self.consoles.iter_mut() {
+                console.clear();
+            }
+        }
+
+        fn set_on_layer(&mut self, _x: i32, _y: i32, _fg: RGB, _bg: RGB, glyph: u8) {
+            self.consoles[self.active].push(glyph);
+        }
+    }
+
+    #[test]
+    fn glyphs_drawn_before_the_first_sprite_still_go_on_the_glyph_console() {
+        let white = RGB::named(rltk::WHITE);
+        let mut ctx = FakeLayers { consoles: vec![Vec::new(), Vec::new()], active: TILE_CONSOLE };
+        clear_frame(&mut ctx);
+        ctx.set_on_layer(0, 0, white, white, b'@');
+        draw_sprite(&mut ctx, 0, 0, white, 7);
+        ctx.set_on_layer(1, 0, white, white, b'o');
+
+        assert_eq!(ctx.consoles[TILE_CONSOLE], vec![7]);
+        assert_eq!(ctx.consoles[GLYPH_CONSOLE], vec![b'@', b'o']);
+
+        // with a single console there's no tile layer, and sprites are dropped
+        let mut ctx = FakeLayers { consoles: vec![Vec::new()], active: 0 };
+        clear_frame(&mut ctx);
+        draw_sprite(&mut ctx, 0, 0, white, 7);
+        ctx.set_on_layer(0, 0, white, white, b'@');
+        assert_eq!(ctx.consoles[0], vec![b'@']);
+    }
+}
@@ -1,3 +1,170 @@
+use rltk::RGB;
+use crate::components::Renderable;
+use crate::options::Verbosity;
+
+/// How many entries the log keeps before the oldest are dropped.
+pub const MAX_LOG_ENTRIES: usize = 200;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum LogCategory {
+    General,
+    Combat,
+    Items
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct LogFragment {
+    pub text: String,
+    pub color: RGB
+}
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+    pub category: LogCategory,
+    pub fragments: Vec<LogFragment>,
+    /// The least verbose setting that still records this entry.
+    pub verbosity: Verbosity,
+    /// How many times in a row this same message was logged.
+    pub count: u32
+}
+
+/// Newest entries come first.
 pub struct GameLog {
-    pub entries: Vec<String>
+    pub entries: Vec<LogEntry>,
+    pub verbosity: Verbosity
+}
+
+impl GameLog {
+    pub fn new() -> GameLog {
+        GameLog { entries: Vec::new(), verbosity: Verbosity::Normal }
+    }
+
+    /// Logs a plain white message.
+    pub fn log<S: ToString>(&mut self, category: LogCategory, text: S) {
+        LogBuilder::new(category).text(text).log(self);
+    }
+
+    /// Adds an entry, folding it into the newest one if it says exactly the same thing.
+    /// Entries too chatty for the current verbosity are dropped.
+    pub fn push(&mut self, entry: LogEntry) {
+        if entry.verbosity > self.verbosity {
+            return;
+        }
+        if let Some(newest) = self.entries.first_mut() {
+            if newest.category == entry.category && newest.fragments == entry.fragments {
+                newest.count += entry.count;
+                return;
+            }
+        }
+        self.entries.insert(0, entry);
+        self.entries.truncate(MAX_LOG_ENTRIES);
+    }
+
+    pub fn filtered(&self, category: Option<LogCategory>) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter().filter(move |entry| category.is_none_or(|category| entry.category == category))
+    }
+}
+
+/// Puts a log entry together a piece at a time, each piece in its own color.
+pub struct LogBuilder {
+    category: LogCategory,
+    fragments: Vec<LogFragment>,
+    verbosity: Verbosity
+}
+
+impl LogBuilder {
+    pub fn new(category: LogCategory) -> LogBuilder {
+        LogBuilder { category, fragments: Vec::new(), verbosity: Verbosity::Normal }
+    }
+
+    /// Worth recording even in the terse log.
+    pub fn major(mut self) -> LogBuilder {
+        self.verbosity = Verbosity::Terse;
+        self
+    }
+
+    /// Only recorded in the verbose log.
+    pub fn minor(mut self) -> LogBuilder {
+        self.verbosity = Verbosity::Verbose;
+        self
+    }
+
+    pub fn text<S: ToString>(self, text: S) -> LogBuilder {
+        self.colored(text, RGB::named(rltk::WHITE))
+    }
+
+    pub fn colored<S: ToString>(mut self, text: S, color: RGB) -> LogBuilder {
+        self.fragments.push(LogFragment { text: text.to_string(), color });
+        self
+    }
+
+    /// An entity's name, in the color it's drawn in.
+    pub fn name<S: ToString>(self, name: S, renderable: Option<&Renderable>) -> LogBuilder {
+        let color = renderable.map_or(RGB::named(rltk::WHITE), |render| render.fg);
+        self.colored(name, color)
+    }
+
+    pub fn damage(self, amount: i32) -> LogBuilder {
+        self.colored(format!("{} hp", amount), RGB::named(rltk::RED))
+    }
+
+    pub fn log(self, log: &mut GameLog) {
+        log.push(LogEntry { category: self.category, fragments: self.fragments, verbosity: self.verbosity, count: 1 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(entry: &LogEntry) -> String {
+        let text: String = entry.fragments.iter().map(|fragment| fragment.text.as_str()).collect();
+        if entry.count > 1 {
+            format!("{} x{}", text, entry.count)
+        } else {
+            text
+        }
+    }
+
+    #[test]
+    fn repeated_messages_are_coalesced() {
+        let mut log = GameLog::new();
+        for _ in 0..3 {
+            LogBuilder::new(LogCategory::Combat).text("Goblin hits you").log(&mut log);
+        }
+        log.log(LogCategory::General, "You climb down to the next level.");
+        LogBuilder::new(LogCategory::Combat).text("Goblin hits you").log(&mut log);
+
+        let texts: Vec<String> = log.entries.iter().map(text).collect();
+        assert_eq!(texts, vec!["Goblin hits you", "You climb down to the next level.", "Goblin hits you x3"]);
+    }
+
+    #[test]
+    fn chatter_is_dropped_below_its_verbosity() {
+        let mut log = GameLog::new();
+        log.verbosity = Verbosity::Terse;
+        LogBuilder::new(LogCategory::Combat).text("Goblin is unable to hurt you").minor().log(&mut log);
+        LogBuilder::new(LogCategory::Combat).text("Goblin hits you").log(&mut log);
+        LogBuilder::new(LogCategory::Combat).text("Goblin is dead").major().log(&mut log);
+        assert_eq!(log.entries.iter().map(text).collect::<Vec<String>>(), vec!["Goblin is dead"]);
+
+        log.verbosity = Verbosity::Verbose;
+        LogBuilder::new(LogCategory::Combat).text("Goblin is unable to hurt you").minor().log(&mut log);
+        assert_eq!(log.entries.len(), 2);
+    }
+
+    #[test]
+    fn history_is_bounded_and_filterable() {
+        let mut log = GameLog::new();
+        for i in 0..MAX_LOG_ENTRIES + 50 {
+            let category = if i % 2 == 0 { LogCategory::Combat } else { LogCategory::Items };
+            log.log(category, format!("message {}", i));
+        }
+
+        assert_eq!(log.entries.len(), MAX_LOG_ENTRIES);
+        assert_eq!(text(&log.entries[0]), format!("message {}", MAX_LOG_ENTRIES + 49));
+        assert_eq!(log.filtered(Some(LogCategory::Items)).count(), MAX_LOG_ENTRIES / 2);
+        assert!(log.filtered(Some(LogCategory::Combat)).all(|entry| entry.category == LogCategory::Combat));
+        assert_eq!(log.filtered(None).count(), MAX_LOG_ENTRIES);
+    }
 }
@@ -1,7 +1,5 @@
-use rltk::{Point, RandomNumberGenerator, Algorithm2D, BaseMap};
+use rltk::{Point, Algorithm2D, BaseMap, RGB};
 use specs::prelude::*;
-use std::cmp::{min, max};
-use crate::rect::Rect;
 
 
 pub const MAPWIDTH: i32 = 80;
@@ -9,55 +7,56 @@ pub const MAPHEIGHT: i32 = 43;
 pub const MAPCOUNT: i32 = MAPWIDTH * MAPHEIGHT;
 
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
 pub enum TileType {
-    Wall, Floor
+    Wall, Floor, DownStairs, Sand, Road, WoodFloor
 }
 
+#[derive(Clone)]
 pub struct GameMap {
     pub tiles: Vec<TileType>,
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
     pub blocked: Vec<bool>,
+    /// Tiles something stands on that can't be seen through, such as a closed door.
+    pub view_blocked: Vec<bool>,
+    /// Closed doors that aren't locked. Paths may go through them, since walking into
+    /// one opens it.
+    pub openable_doors: Vec<bool>,
     pub tile_content: Vec<Vec<Entity>>,
-    pub rooms: Vec<Rect>,
+    /// How brightly lit each tile is this turn, from 0 for pitch dark to 1.
+    pub light: Vec<f32>,
+    /// The light everywhere before any lamp or torch is counted; daylight on the surface.
+    pub ambient_light: f32,
+    /// Open sky overhead, so the time of day changes the light and who's about.
+    pub outdoors: bool,
+    /// Walls that are really hidden passages, until the player finds them.
+    pub secret_doors: Vec<bool>,
+    /// The glyph of whatever the player last saw on each tile, drawn once it's out of sight.
+    pub remembered_glyphs: Vec<Option<(u8, RGB)>>,
     pub width: i32,
     pub height: i32,
+    pub depth: i32,
 }
 
 impl GameMap {
-    fn draw_room(&mut self, room: &Rect) {
-        for y in room.y1 + 1 ..= room.y2 {
-            for x in room.x1 + 1 ..= room.x2 {
-                let index = self.xy_idx(x, y);
-                self.tiles[index] = TileType::Floor;
-            }
-        }
-    }
-
-    fn draw_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
-        let minx = min(x1, x2);
-        let maxx = max(x1, x2);
-        self.draw_room(&Rect::new(minx, y, maxx - minx, 1));
-    }
-
-    fn draw_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
-        let miny = min(y1, y2);
-        let maxy = max(y1, y2);
-        self.draw_room(&Rect::new(x, miny, 1, maxy - miny));
-    }
-
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> GameMap {
+    pub fn new(depth: i32) -> GameMap {
         GameMap {
             tiles: vec![TileType::Wall; MAPCOUNT as usize],
             revealed_tiles: vec![false; MAPCOUNT as usize],
             visible_tiles: vec![false; MAPCOUNT as usize],
             blocked: vec![true; MAPCOUNT as usize],
+            view_blocked: vec![false; MAPCOUNT as usize],
+            openable_doors: vec![false; MAPCOUNT as usize],
             tile_content: vec![Vec::<Entity>::new(); MAPCOUNT as usize],
-            rooms: Vec::<Rect>::new(),
+            light: vec![0.0; MAPCOUNT as usize],
+            ambient_light: 0.0,
+            outdoors: false,
+            secret_doors: vec![false; MAPCOUNT as usize],
+            remembered_glyphs: vec![None; MAPCOUNT as usize],
             width: MAPWIDTH,
-            height: MAPHEIGHT
+            height: MAPHEIGHT,
+            depth
         }
     }
 
@@ -65,56 +64,29 @@ impl GameMap {
         (y * self.width + x) as usize
     }
 
-    pub fn populate_with_random_rooms(&mut self) {
-        const MAX_ROOMS: i32 = 30;
-        const MIN_SIZE: i32 = 6;
-        const MAX_SIZE: i32 = 10;
-
-        let mut rng = RandomNumberGenerator::new();
-        let mut rooms = Vec::<Rect>::new();
-
-        'outer: for _ in 0..MAX_ROOMS {
-            let w = rng.range(MIN_SIZE, MAX_SIZE);
-            let h = rng.range(MIN_SIZE, MAX_SIZE);
-            let x = rng.roll_dice(1, self.width - w - 1) - 1;
-            let y = rng.roll_dice(1, self.height - h - 1) - 1;
-            let new_room = Rect::new(x, y, w, h);
-
-            for room in rooms.iter() {
-                if new_room.intersect(room) {
-                    continue 'outer;
-                }
-            }
-
-            self.draw_room(&new_room);
-            if !rooms.is_empty() {
-                let (new_x, new_y) = new_room.center();
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-                if rng.roll_dice(1, 2) == 1 {
-                    self.draw_horizontal_tunnel(prev_x, new_x, prev_y);
-                    self.draw_vertical_tunnel(prev_y, new_y, new_x);
-                } else {
-                    self.draw_vertical_tunnel(prev_y, new_y, prev_x);
-                    self.draw_horizontal_tunnel(prev_x, new_x, new_y);
-                }
-            }
-
-            rooms.push(new_room);
-        }
-
-        self.rooms = rooms;
-    }
-
     pub fn populate_blocked(&mut self) {
         for (i, &tile) in self.tiles.iter().enumerate() {
             self.blocked[i] = tile == TileType::Wall;
         }
     }
 
+    /// Opens up a secret passage once it has been found.
+    pub fn reveal_secret_door(&mut self, idx: usize) {
+        self.secret_doors[idx] = false;
+        self.tiles[idx] = TileType::Floor;
+        self.blocked[idx] = false;
+    }
+
     pub fn clear_content_index(&mut self) {
         for content in self.tile_content.iter_mut() {
             content.clear();
         }
+        for blocked in self.view_blocked.iter_mut() {
+            *blocked = false;
+        }
+        for door in self.openable_doors.iter_mut() {
+            *door = false;
+        }
     }
 
     fn is_exit_valid(&self, x: i32, y: i32) -> bool {
@@ -122,7 +94,7 @@ impl GameMap {
             false
         } else {
             let idx = self.xy_idx(x, y);
-            !self.blocked[idx]
+            !self.blocked[idx] || self.openable_doors[idx]
         }
     }
 }
@@ -135,7 +107,7 @@ impl Algorithm2D for GameMap {
 
 impl BaseMap for GameMap {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx as usize] == TileType::Wall
+        self.tiles[idx] == TileType::Wall || self.view_blocked[idx]
     }
 
     fn get_available_exits(&self, idx: usize) -> Vec<(usize, f32)> {
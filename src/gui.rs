@@ -1,7 +1,14 @@
-use rltk::{ RGB, Point, Rltk, Console, VirtualKeyCode };
-use crate::components::{CombatStats, Player, Position, Name, InBackpack};
-use crate::game_log::GameLog;
-use crate::game_map::GameMap;
+use rltk::{ RGB, Point, VirtualKeyCode };
+use crate::components::{
+    CombatStats, Player, Position, Name, InBackpack, Monster, Item, Potion, Door, Locked, Hidden, Trap,
+    Immobilized, Poisoned
+};
+use crate::game_log::{GameLog, LogEntry, LogCategory};
+use crate::game_map::{GameMap, TileType};
+use crate::clock::GameClock;
+use crate::frontend::Frontend;
+use crate::keymap::{Command, Keymap, key_name};
+use crate::options::{Options, Font, Verbosity, Difficulty, cycle};
 use specs::prelude::*;
 
 
@@ -13,12 +20,19 @@ pub enum ItemMenuResult {
 }
 
 
-pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
+pub fn draw_ui(ecs: &World, ctx: &mut dyn Frontend) {
     ctx.draw_box(0, 43, 79, 6, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
 
     let combat_stats = ecs.read_storage::<CombatStats>();
     let players = ecs.read_storage::<Player>();
     let log = ecs.fetch::<GameLog>();
+    let map = ecs.fetch::<GameMap>();
+
+    let depth = format!("Depth: {}", map.depth);
+    ctx.print_color(2, 43, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &depth);
+
+    let time = format!(" {} ", ecs.fetch::<GameClock>().describe());
+    ctx.print_color(77 - time.len() as i32, 49, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &time);
 
     for (_player, stats) in (&players, &combat_stats).join() {
         let health = format!("HP: {} / {} ", stats.hp, stats.max_hp);
@@ -26,28 +40,26 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
         ctx.draw_bar_horizontal(28, 43, 51, stats.hp, stats.max_hp, RGB::named(rltk::RED), RGB::named(rltk::BLACK));
     }
 
-    let mut y = 44;
-    for s in log.entries.iter() {
-        if y < 49 {
-            ctx.print(2, y, s);
-        }
-        y += 1;
+    for (y, entry) in (44..49).zip(log.entries.iter()) {
+        print_log_entry(ctx, 2, y, 76, entry);
     }
 
     draw_tooltips(ecs, ctx);
 }
 
 
-pub fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
+pub fn draw_tooltips(ecs: &World, ctx: &mut dyn Frontend) {
     let map = ecs.fetch::<GameMap>();
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
+    let hidden = ecs.read_storage::<Hidden>();
 
     let (mousex, mousey) = ctx.mouse_pos();
-    if mousex < map.width && mousey < map.height {
+    if mousex >= 0 && mousex < map.width && mousey >= 0 && mousey < map.height
+        && map.visible_tiles[map.xy_idx(mousex, mousey)] {
         let mut tooltip: Vec<String> = Vec::new();
 
-        for (name, position) in (&names, &positions).join() {
+        for (name, position, _not_hidden) in (&names, &positions, !&hidden).join() {
             if position.x == mousex && position.y == mousey {
                 tooltip.push(name.name.to_string());
             }
@@ -65,29 +77,25 @@ pub fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
             if mousex > 40 {
                 let arrow_pos = Point::new(mousex - 2, mousey);
                 let leftx = mousex - width;
-                let mut y = mousey;
-                for s in tooltip.iter() {
+                for (y, s) in (mousey..).zip(tooltip.iter()) {
                     ctx.print_color(leftx, y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), s);
                     let padding = (width - s.len() as i32) - 1;
                     for i in 0..padding {
-                        ctx.print_color(arrow_pos.x - i, y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), &" ".to_string());
+                        ctx.print_color(arrow_pos.x - i, y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), " ");
                     }
-                    y += 1;
                 }
-                ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), &"->".to_string());
+                ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), "->");
             } else {
                 let arrow_pos = Point::new(mousex + 1, mousey);
                 let leftx = mousex + 3;
-                let mut y = mousey;
-                for s in tooltip.iter() {
+                for (y, s) in (mousey..).zip(tooltip.iter()) {
                     ctx.print_color(leftx + 1, y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), s);
                     let padding = (width - s.len() as i32) - 1;
                     for i in 0..padding {
-                        ctx.print_color(arrow_pos.x + 1 + i, y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), &" ".to_string());
+                        ctx.print_color(arrow_pos.x + 1 + i, y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), " ");
                     }
-                    y += 1;
                 }
-                ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), &"<-".to_string());
+                ctx.print_color(arrow_pos.x, arrow_pos.y, RGB::named(rltk::WHITE), RGB::named(rltk::GREY), "<-");
             }
 
         }
@@ -96,17 +104,410 @@ pub fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
 }
 
 
-pub fn show_inventory(ecs: &mut World, ctx: &mut Rltk) -> ItemMenuResult {
+/// Prints an entry's fragments in their colors, cut off after `width` characters.
+fn print_log_entry(ctx: &mut dyn Frontend, x: i32, y: i32, width: usize, entry: &LogEntry) {
+    let mut column = 0;
+    for fragment in entry.fragments.iter() {
+        let text: String = fragment.text.chars().take(width.saturating_sub(column)).collect();
+        ctx.print_color(x + column as i32, y, fragment.color, RGB::named(rltk::BLACK), &text);
+        column += text.chars().count();
+    }
+    if entry.count > 1 {
+        let repeats: String = format!(" x{}", entry.count).chars().take(width.saturating_sub(column)).collect();
+        ctx.print_color(x + column as i32, y, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), &repeats);
+    }
+}
+
+
+const LOG_PAGE: usize = 46;
+
+/// Where the full-screen log viewer is scrolled to and which category it shows.
+#[derive(Default)]
+pub struct LogViewer {
+    pub scroll: usize,
+    pub filter: Option<LogCategory>
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LogViewerResult {
+    Open,
+    Closed
+}
+
+
+/// The whole message history, newest first. Up and down scroll a line, page up and
+/// page down a screen, tab cycles the category filter and escape closes it.
+pub fn show_log(ecs: &World, ctx: &mut dyn Frontend, viewer: &mut LogViewer) -> LogViewerResult {
+    let log = ecs.fetch::<GameLog>();
+    let count = log.filtered(viewer.filter).count();
+    let max_scroll = count.saturating_sub(LOG_PAGE);
+
+    if let Some(key) = ctx.key() {
+        if ecs.fetch::<Keymap>().command_for(key) == Some(Command::MessageLog) {
+            return LogViewerResult::Closed;
+        }
+        match key {
+            VirtualKeyCode::Escape => return LogViewerResult::Closed,
+            VirtualKeyCode::Up | VirtualKeyCode::K => viewer.scroll = viewer.scroll.saturating_sub(1),
+            VirtualKeyCode::Down | VirtualKeyCode::J => viewer.scroll += 1,
+            VirtualKeyCode::PageUp => viewer.scroll = viewer.scroll.saturating_sub(LOG_PAGE),
+            VirtualKeyCode::PageDown => viewer.scroll += LOG_PAGE,
+            VirtualKeyCode::Tab => {
+                viewer.filter = match viewer.filter {
+                    None => Some(LogCategory::General),
+                    Some(LogCategory::General) => Some(LogCategory::Combat),
+                    Some(LogCategory::Combat) => Some(LogCategory::Items),
+                    Some(LogCategory::Items) => None
+                };
+                viewer.scroll = 0;
+            },
+            _ => {}
+        }
+    }
+    viewer.scroll = viewer.scroll.min(max_scroll);
+
+    ctx.draw_box(0, 0, 79, 49, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(2, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Message Log");
+    let filter = match viewer.filter {
+        None => "All",
+        Some(LogCategory::General) => "General",
+        Some(LogCategory::Combat) => "Combat",
+        Some(LogCategory::Items) => "Items"
+    };
+    ctx.print_color(60, 0, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &format!("Showing: {}", filter));
+
+    for (y, entry) in (2..).zip(log.filtered(viewer.filter).skip(viewer.scroll).take(LOG_PAGE)) {
+        print_log_entry(ctx, 2, y, 76, entry);
+    }
+    if count > 0 {
+        let last = usize::min(viewer.scroll + LOG_PAGE, count);
+        ctx.print_color(60, 49, RGB::named(rltk::GREY), RGB::named(rltk::BLACK),
+            &format!("{}-{} of {}", viewer.scroll + 1, last, count));
+    }
+    ctx.print_color(2, 49, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "TAB filter  PGUP/PGDN scroll  ESC close");
+
+    LogViewerResult::Open
+}
+
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum LookResult {
+    Looking,
+    Done,
+    Travel(Point)
+}
+
+
+/// Moves a cursor around the map with the movement keys and describes what the
+/// player can see under it. Tiles out of sight get no description at all. Enter
+/// sets off walking to the cursor.
+pub fn look_mode(ecs: &World, ctx: &mut dyn Frontend, cursor: &mut Point) -> LookResult {
+    let map = ecs.fetch::<GameMap>();
+
+    if let Some(key) = ctx.key() {
+        let command = ecs.fetch::<Keymap>().command_for(key);
+        if let Some((delta_x, delta_y)) = command.and_then(Command::direction) {
+            cursor.x = (cursor.x + delta_x).clamp(0, map.width - 1);
+            cursor.y = (cursor.y + delta_y).clamp(0, map.height - 1);
+        } else if key == VirtualKeyCode::Escape || command == Some(Command::Look) {
+            return LookResult::Done;
+        } else if key == VirtualKeyCode::Return {
+            return LookResult::Travel(*cursor);
+        }
+    }
+
+    let idx = map.xy_idx(cursor.x, cursor.y);
+    let mut lines: Vec<(RGB, String)> = Vec::new();
+    if map.visible_tiles[idx] {
+        lines.push((RGB::named(rltk::GREY), tile_name(map.tiles[idx]).to_string()));
+        lines.extend(describe_entities_at(ecs, cursor.x, cursor.y));
+    }
+
+    let x = if cursor.x > 40 { 1 } else { 48 };
+    let height = lines.len().max(1) as i32 + 1;
+    ctx.draw_box(x, 1, 30, height, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(x + 2, 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Look");
+    ctx.print_color(x + 2, 1 + height, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ENTER travel  ESCAPE done");
+    if lines.is_empty() {
+        ctx.print_color(x + 2, 2, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), "You can't see there.");
+    }
+    for (y, (fg, line)) in (2..).zip(lines.iter()) {
+        ctx.print_color(x + 2, y, *fg, RGB::named(rltk::BLACK), line);
+    }
+
+    ctx.set(cursor.x, cursor.y, RGB::named(rltk::BLACK), RGB::named(rltk::MAGENTA), rltk::to_cp437('X'));
+    LookResult::Looking
+}
+
+
+fn tile_name(tile: TileType) -> &'static str {
+    match tile {
+        TileType::Wall => "A wall",
+        TileType::Floor => "Bare floor",
+        TileType::DownStairs => "Stairs leading down",
+        TileType::Sand => "Sand",
+        TileType::Road => "A dusty road",
+        TileType::WoodFloor => "Wooden floorboards"
+    }
+}
+
+
+fn health_description(stats: &CombatStats) -> &'static str {
+    let fraction = stats.hp as f32 / stats.max_hp as f32;
+    if stats.hp >= stats.max_hp {
+        "unhurt"
+    } else if fraction > 0.66 {
+        "lightly wounded"
+    } else if fraction > 0.33 {
+        "wounded"
+    } else if fraction > 0.15 {
+        "badly wounded"
+    } else {
+        "nearly dead"
+    }
+}
+
+
+fn describe_entities_at(ecs: &World, x: i32, y: i32) -> Vec<(RGB, String)> {
+    let names = ecs.read_storage::<Name>();
+    let positions = ecs.read_storage::<Position>();
+    let stats = ecs.read_storage::<CombatStats>();
+    let monsters = ecs.read_storage::<Monster>();
+    let items = ecs.read_storage::<Item>();
+    let potions = ecs.read_storage::<Potion>();
+    let doors = ecs.read_storage::<Door>();
+    let locks = ecs.read_storage::<Locked>();
+    let hidden = ecs.read_storage::<Hidden>();
+    let traps = ecs.read_storage::<Trap>();
+    let immobilized = ecs.read_storage::<Immobilized>();
+    let poisoned = ecs.read_storage::<Poisoned>();
+    let entities = ecs.entities();
+
+    let mut lines = Vec::new();
+    for (entity, name, position, _not_hidden) in (&entities, &names, &positions, !&hidden).join() {
+        if position.x != x || position.y != y {
+            continue;
+        }
+        lines.push((RGB::named(rltk::WHITE), name.name.to_string()));
+        if let Some(stats) = stats.get(entity) {
+            lines.push((RGB::named(rltk::RED), format!("  {}", health_description(stats))));
+        }
+        if monsters.get(entity).is_some() {
+            lines.push((RGB::named(rltk::ORANGE), "  hostile".to_string()));
+        }
+        if immobilized.contains(entity) {
+            lines.push((RGB::named(rltk::YELLOW), "  held fast".to_string()));
+        }
+        if poisoned.contains(entity) {
+            lines.push((RGB::named(rltk::GREEN), "  poisoned".to_string()));
+        }
+        if items.get(entity).is_some() {
+            lines.push((RGB::named(rltk::CYAN), "  can be picked up".to_string()));
+        }
+        if let Some(potion) = potions.get(entity) {
+            lines.push((RGB::named(rltk::CYAN), format!("  heals {} hp", potion.heal_amount)));
+        }
+        if let Some(door) = doors.get(entity) {
+            let state = if door.open { "open" } else if locks.contains(entity) { "locked" } else { "closed" };
+            lines.push((RGB::named(rltk::SADDLEBROWN), format!("  {}", state)));
+        }
+        if traps.contains(entity) {
+            lines.push((RGB::named(rltk::ORANGE), "  trap".to_string()));
+        }
+    }
+    lines
+}
+
+
+/// Which command the rebinding screen has selected, and whether it's waiting for
+/// the key to bind to it.
+#[derive(Default)]
+pub struct KeybindingMenu {
+    pub selected: usize,
+    pub waiting_for_key: bool,
+    pub message: Option<String>
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum KeybindingMenuResult {
+    Open,
+    Closed
+}
+
+
+/// Lists every command with its keys. Up and down pick a command, enter waits for
+/// a key to add to it, delete clears its keys and escape closes the screen. A key
+/// that already belonged to another command is moved, and the screen says so.
+pub fn keybindings_menu(ecs: &mut World, ctx: &mut dyn Frontend, menu: &mut KeybindingMenu) -> KeybindingMenuResult {
+    let mut keymap = ecs.fetch_mut::<Keymap>();
+    let selected = Command::ALL[menu.selected];
+
+    if let Some(key) = ctx.key() {
+        if menu.waiting_for_key {
+            menu.waiting_for_key = false;
+            menu.message = if key == VirtualKeyCode::Escape {
+                None
+            } else if !Keymap::is_bindable(key) {
+                Some(format!("{} can't be bound.", key_name(key)))
+            } else {
+                match keymap.bind(key, selected) {
+                    Some(previous) => Some(format!("{} moved from {} to {}.", key_name(key), previous.description(), selected.description())),
+                    None => Some(format!("{} now does {}.", key_name(key), selected.description()))
+                }
+            };
+        } else {
+            match key {
+                VirtualKeyCode::Escape => return KeybindingMenuResult::Closed,
+                VirtualKeyCode::Up => menu.selected = menu.selected.saturating_sub(1),
+                VirtualKeyCode::Down => menu.selected = usize::min(menu.selected + 1, Command::ALL.len() - 1),
+                VirtualKeyCode::Return => {
+                    menu.waiting_for_key = true;
+                    menu.message = None;
+                },
+                VirtualKeyCode::Delete | VirtualKeyCode::Back => {
+                    keymap.clear(selected);
+                    menu.message = Some(format!("{} has no keys.", selected.description()));
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let height = Command::ALL.len() as i32 + 4;
+    let y = 25 - height / 2;
+    ctx.draw_box(10, y, 60, height, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(13, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Keybindings");
+    for (row, command) in (y + 2..).zip(Command::ALL.iter()) {
+        let highlighted = *command == Command::ALL[menu.selected];
+        let bg = if highlighted { RGB::named(rltk::GREY) } else { RGB::named(rltk::BLACK) };
+        let keys: Vec<String> = keymap.keys_for(*command).into_iter().map(key_name).collect();
+        ctx.print_color(12, row, RGB::named(rltk::WHITE), bg, &format!("{:<18}", command.description()));
+        ctx.print_color(31, row, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), &keys.join(", "));
+    }
+
+    let status = if menu.waiting_for_key {
+        format!("Press a key for {}, ESCAPE to cancel", selected.description())
+    } else {
+        menu.message.clone().unwrap_or_default()
+    };
+    ctx.print_color(12, y + height - 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &status);
+    ctx.print_color(13, y + height, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ENTER add key  DELETE clear  ESCAPE save");
+
+    KeybindingMenuResult::Open
+}
+
+
+/// Which option is selected, and whether the window title is being typed in.
+#[derive(Default)]
+pub struct OptionsMenu {
+    pub selected: usize,
+    pub editing_title: bool
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum OptionsMenuResult {
+    Open,
+    Closed
+}
+
+const OPTION_ROWS: usize = 6;
+const MAX_TITLE_LENGTH: usize = 40;
+
+
+fn key_to_char(key: VirtualKeyCode, shift: bool) -> Option<char> {
+    let text = match key {
+        VirtualKeyCode::Space => " ".to_string(),
+        VirtualKeyCode::Minus => "-".to_string(),
+        VirtualKeyCode::Period => ".".to_string(),
+        VirtualKeyCode::Comma => ",".to_string(),
+        VirtualKeyCode::Apostrophe => "'".to_string(),
+        _ => key_name(key).trim_start_matches("Key").to_string()
+    };
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if shift => Some(c.to_ascii_uppercase()),
+        (Some(c), None) => Some(c.to_ascii_lowercase()),
+        _ => None
+    }
+}
+
+
+/// Up and down pick an option, left and right change it, enter starts typing a new
+/// window title and escape closes the screen. The font and title are only read when
+/// the game starts.
+pub fn options_menu(ecs: &mut World, ctx: &mut dyn Frontend, menu: &mut OptionsMenu) -> OptionsMenuResult {
+    let mut options = ecs.fetch_mut::<Options>();
+
+    if let Some(key) = ctx.key() {
+        if menu.editing_title {
+            match key {
+                VirtualKeyCode::Return | VirtualKeyCode::Escape => menu.editing_title = false,
+                VirtualKeyCode::Back => { options.title.pop(); },
+                _ => {
+                    if let Some(c) = key_to_char(key, ctx.shift()) {
+                        if options.title.chars().count() < MAX_TITLE_LENGTH {
+                            options.title.push(c);
+                        }
+                    }
+                }
+            }
+        } else {
+            let forward = key != VirtualKeyCode::Left;
+            match key {
+                VirtualKeyCode::Escape => return OptionsMenuResult::Closed,
+                VirtualKeyCode::Up => menu.selected = menu.selected.saturating_sub(1),
+                VirtualKeyCode::Down => menu.selected = usize::min(menu.selected + 1, OPTION_ROWS - 1),
+                VirtualKeyCode::Left | VirtualKeyCode::Right | VirtualKeyCode::Return => match menu.selected {
+                    0 => menu.editing_title = key == VirtualKeyCode::Return,
+                    1 => options.font = cycle(&Font::ALL, options.font, forward),
+                    2 => options.scanlines = !options.scanlines,
+                    3 => options.tiles = !options.tiles,
+                    4 => options.verbosity = cycle(&Verbosity::ALL, options.verbosity, forward),
+                    _ => options.difficulty = cycle(&Difficulty::ALL, options.difficulty, forward)
+                },
+                _ => {}
+            }
+        }
+    }
+
+    let title = if menu.editing_title { format!("{}_", options.title) } else { options.title.clone() };
+    let rows = [
+        ("Window title", title, true),
+        ("Font", options.font.name().to_string(), true),
+        ("Scanlines", if options.scanlines { "on" } else { "off" }.to_string(), false),
+        ("Tileset", if options.tiles { "on" } else { "off" }.to_string(), true),
+        ("Message verbosity", options.verbosity.name().to_string(), false),
+        ("Difficulty", options.difficulty.name().to_string(), false)
+    ];
+
+    let y = 18;
+    ctx.draw_box(10, y, 60, OPTION_ROWS as i32 + 3, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
+    ctx.print_color(13, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "Options");
+    for (row, (i, (label, value, on_restart))) in (y + 2..).zip(rows.iter().enumerate()) {
+        let bg = if i == menu.selected { RGB::named(rltk::GREY) } else { RGB::named(rltk::BLACK) };
+        ctx.print_color(12, row, RGB::named(rltk::WHITE), bg, &format!("{:<18}", label));
+        ctx.print_color(31, row, RGB::named(rltk::CYAN), RGB::named(rltk::BLACK), value);
+        if *on_restart {
+            ctx.print_color(56, row, RGB::named(rltk::GREY), RGB::named(rltk::BLACK), "(on restart)");
+        }
+    }
+    ctx.print_color(13, y + OPTION_ROWS as i32 + 3, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "LEFT/RIGHT change  ENTER edit  ESCAPE save");
+
+    OptionsMenuResult::Open
+}
+
+
+pub fn show_inventory(ecs: &mut World, ctx: &mut dyn Frontend) -> ItemMenuResult {
     show_inventory_menu(ecs, ctx, "Inventory")
 }
 
 
-pub fn drop_item_menu(ecs: &mut World, ctx: &mut Rltk) -> ItemMenuResult {
+pub fn drop_item_menu(ecs: &mut World, ctx: &mut dyn Frontend) -> ItemMenuResult {
     show_inventory_menu(ecs, ctx, "Drop Item")
 }
 
 
-fn show_inventory_menu(ecs: &mut World, ctx: &mut Rltk, title: &str) -> ItemMenuResult {
+fn show_inventory_menu(ecs: &mut World, ctx: &mut dyn Frontend, title: &str) -> ItemMenuResult {
     let player_entity = ecs.fetch::<Entity>();
     let names = ecs.read_storage::<Name>();
     let backpack = ecs.read_storage::<InBackpack>();
@@ -115,30 +516,28 @@ fn show_inventory_menu(ecs: &mut World, ctx: &mut Rltk, title: &str) -> ItemMenu
     let inventory = (&backpack, &names).join().filter(|item| item.0.owner == *player_entity);
     let count = inventory.count() as i32;
 
-    let mut y = 25 - (count / 2);
+    let y = 25 - (count / 2);
     ctx.draw_box(15, y - 2, 31, count + 3, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK));
     ctx.print_color(18, y - 2, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), title);
     ctx.print_color(18, y + count + 1, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), "ESCAPE to cancel");
 
     let mut equippable = Vec::<Entity>::new();
-    let mut j = 0;
-    for (entity, _pack, name) in (&entities, &backpack, &names).join().filter(|item| item.1.owner == *player_entity) {
+    for (j, (entity, _pack, name)) in (&entities, &backpack, &names).join().filter(|item| item.1.owner == *player_entity).enumerate() {
+        let y = y + j as i32;
         ctx.set(17, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437('('));
         ctx.set(18, y, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), 97+j as u8);
         ctx.set(19, y, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK), rltk::to_cp437(')'));
 
-        ctx.print(21, y, &name.name.to_string());
+        ctx.print(21, y, &name.name);
         equippable.push(entity);
-        y += 1;
-        j += 1;
     }
 
-    if let Some(key) = ctx.key {
+    if let Some(key) = ctx.key() {
         match key {
             VirtualKeyCode::Escape => ItemMenuResult::Cancel,
             _ => {
                 let selection = rltk::letter_to_option(key);
-                if selection > -1 && selection < count as i32 {
+                if selection > -1 && selection < count {
                     ItemMenuResult::Selected(equippable[selection as usize])
                 } else {
                     ItemMenuResult::NoResponse
@@ -148,4 +547,178 @@ fn show_inventory_menu(ecs: &mut World, ctx: &mut Rltk, title: &str) -> ItemMenu
     } else {
         ItemMenuResult::NoResponse
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame_buffer::FrameBuffer;
+    use crate::frontend::Frontend;
+    use crate::snapshot::{assert_snapshot, fixture_world, frame_to_text, named};
+
+    #[test]
+    fn ui_matches_snapshot() {
+        let ecs = fixture_world();
+        let mut frame = FrameBuffer::new(80, 50);
+        draw_ui(&ecs, &mut frame);
+        assert_snapshot("render_ui", &frame_to_text(&frame));
+    }
+
+    #[test]
+    fn tooltip_names_what_is_under_the_mouse() {
+        let ecs = fixture_world();
+        let mut frame = FrameBuffer::new(80, 50);
+        frame.mouse_pos = (12, 10);
+        draw_tooltips(&ecs, &mut frame);
+        assert_snapshot("render_tooltip_orc", &frame_to_text(&frame));
+    }
+
+    #[test]
+    fn tooltips_stay_quiet_about_things_out_of_sight() {
+        let ecs = fixture_world();
+        let orc_tile = ecs.fetch::<GameMap>().xy_idx(12, 10);
+        ecs.fetch_mut::<GameMap>().visible_tiles[orc_tile] = false;
+
+        let mut frame = FrameBuffer::new(80, 50);
+        frame.mouse_pos = (12, 10);
+        draw_tooltips(&ecs, &mut frame);
+        assert!(frame.cells.iter().all(|cell| cell.glyph == 32));
+    }
+
+    #[test]
+    fn look_mode_describes_what_is_in_view() {
+        let ecs = fixture_world();
+        let orc = named(&ecs, "Orc");
+        ecs.write_storage::<Immobilized>().insert(orc, Immobilized { turns: 2 }).unwrap();
+        ecs.write_storage::<Poisoned>().insert(orc, Poisoned { turns: 3 }).unwrap();
+        let mut frame = FrameBuffer::new(80, 50);
+        let mut cursor = Point::new(11, 10);
+        frame.key = Some(VirtualKeyCode::L);
+        assert!(look_mode(&ecs, &mut frame, &mut cursor) == LookResult::Looking);
+        assert!(cursor == Point::new(12, 10));
+        assert_snapshot("render_look_orc", &frame_to_text(&frame));
+
+        frame.cls();
+        frame.key = None;
+        let mut cursor = Point::new(25, 10);
+        look_mode(&ecs, &mut frame, &mut cursor);
+        assert_snapshot("render_look_out_of_sight", &frame_to_text(&frame));
+
+        frame.key = Some(VirtualKeyCode::Return);
+        assert!(look_mode(&ecs, &mut frame, &mut cursor) == LookResult::Travel(Point::new(25, 10)));
+
+        frame.key = Some(VirtualKeyCode::Escape);
+        assert!(look_mode(&ecs, &mut frame, &mut cursor) == LookResult::Done);
+    }
+
+    #[test]
+    fn log_viewer_scrolls_and_filters() {
+        let ecs = fixture_world();
+        {
+            let mut log = ecs.fetch_mut::<GameLog>();
+            for i in 0..60 {
+                log.log(LogCategory::Items, format!("You find {} gold nuggets.", i));
+            }
+        }
+        let mut viewer = LogViewer::default();
+        let mut frame = FrameBuffer::new(80, 50);
+
+        frame.key = Some(VirtualKeyCode::PageDown);
+        assert!(show_log(&ecs, &mut frame, &mut viewer) == LogViewerResult::Open);
+        assert_eq!(viewer.scroll, 63 - LOG_PAGE);
+        assert_snapshot("render_log_scrolled", &frame_to_text(&frame));
+
+        frame.cls();
+        frame.key = Some(VirtualKeyCode::Tab);
+        show_log(&ecs, &mut frame, &mut viewer);
+        frame.cls();
+        frame.key = Some(VirtualKeyCode::Tab);
+        show_log(&ecs, &mut frame, &mut viewer);
+        assert!(viewer.filter == Some(LogCategory::Combat) && viewer.scroll == 0);
+        assert_snapshot("render_log_combat", &frame_to_text(&frame));
+
+        frame.key = Some(VirtualKeyCode::Escape);
+        assert!(show_log(&ecs, &mut frame, &mut viewer) == LogViewerResult::Closed);
+    }
+
+    #[test]
+    fn rebinding_moves_a_key_between_commands() {
+        let mut ecs = fixture_world();
+        let mut menu = KeybindingMenu::default();
+        let mut frame = FrameBuffer::new(80, 50);
+
+        frame.key = Some(VirtualKeyCode::Return);
+        keybindings_menu(&mut ecs, &mut frame, &mut menu);
+        assert!(menu.waiting_for_key);
+
+        frame.key = Some(VirtualKeyCode::G);
+        keybindings_menu(&mut ecs, &mut frame, &mut menu);
+        assert_eq!(ecs.fetch::<Keymap>().command_for(VirtualKeyCode::G), Some(Command::MoveWest));
+        assert!(ecs.fetch::<Keymap>().keys_for(Command::PickUp).is_empty());
+
+        frame.cls();
+        frame.key = None;
+        keybindings_menu(&mut ecs, &mut frame, &mut menu);
+        assert_snapshot("render_keybindings", &frame_to_text(&frame));
+
+        frame.key = Some(VirtualKeyCode::Escape);
+        assert!(keybindings_menu(&mut ecs, &mut frame, &mut menu) == KeybindingMenuResult::Closed);
+    }
+
+    #[test]
+    fn options_menu_changes_settings_and_edits_the_title() {
+        let mut ecs = fixture_world();
+        let mut menu = OptionsMenu::default();
+        let mut frame = FrameBuffer::new(80, 50);
+
+        let keys = [
+            (VirtualKeyCode::Return, false), (VirtualKeyCode::Back, false), (VirtualKeyCode::Back, false),
+            (VirtualKeyCode::Key2, false), (VirtualKeyCode::Return, false),
+            (VirtualKeyCode::Down, false), (VirtualKeyCode::Right, false),
+            (VirtualKeyCode::Down, false), (VirtualKeyCode::Return, false),
+            (VirtualKeyCode::Down, false), (VirtualKeyCode::Right, false),
+            (VirtualKeyCode::Down, false), (VirtualKeyCode::Left, false),
+            (VirtualKeyCode::Down, false), (VirtualKeyCode::Right, false)
+        ];
+        for (key, shift) in keys.iter() {
+            frame.key = Some(*key);
+            frame.shift = *shift;
+            assert!(options_menu(&mut ecs, &mut frame, &mut menu) == OptionsMenuResult::Open);
+        }
+        {
+            let options = ecs.fetch::<Options>();
+            assert_eq!(options.title, "Hello bitch2");
+            assert_eq!(options.font, Font::Vga8x16);
+            assert!(!options.scanlines);
+            assert!(options.tiles);
+            assert_eq!(options.verbosity, Verbosity::Terse);
+            assert_eq!(options.difficulty, Difficulty::Hard);
+        }
+
+        frame.cls();
+        frame.key = None;
+        options_menu(&mut ecs, &mut frame, &mut menu);
+        assert_snapshot("render_options", &frame_to_text(&frame));
+
+        frame.key = Some(VirtualKeyCode::Escape);
+        assert!(options_menu(&mut ecs, &mut frame, &mut menu) == OptionsMenuResult::Closed);
+    }
+
+    #[test]
+    fn inventory_menu_matches_snapshot_and_picks_by_letter() {
+        let mut ecs = fixture_world();
+        let mut frame = FrameBuffer::new(80, 50);
+        assert!(show_inventory(&mut ecs, &mut frame) == ItemMenuResult::NoResponse);
+        assert_snapshot("render_inventory", &frame_to_text(&frame));
+
+        frame.cls();
+        frame.key = Some(VirtualKeyCode::B);
+        assert!(matches!(show_inventory(&mut ecs, &mut frame), ItemMenuResult::Selected(_)));
+
+        frame.key = Some(VirtualKeyCode::C);
+        assert!(show_inventory(&mut ecs, &mut frame) == ItemMenuResult::NoResponse);
+
+        frame.key = Some(VirtualKeyCode::Escape);
+        assert!(show_inventory(&mut ecs, &mut frame) == ItemMenuResult::Cancel);
+    }
+}
@@ -4,7 +4,7 @@ use crate::components::{
     Name, InBackpack, Position,
     Potion, WantsToDrinkPotion, CombatStats
 };
-use crate::game_log::GameLog;
+use crate::game_log::{GameLog, LogCategory};
 
 pub struct ItemCollectionSystem;
 pub struct ItemDropSystem;
@@ -34,7 +34,7 @@ impl<'a> System<'a> for ItemCollectionSystem {
             backpack.insert(pickup.item, InBackpack { owner: pickup.collected_by }).expect("Unable to add item to backpack");
 
             if pickup.collected_by == *player_entity {
-                gamelog.entries.insert(0, format!("You pick up the {}.", names.get(pickup.item).unwrap().name));
+                gamelog.log(LogCategory::Items, format!("You pick up the {}.", names.get(pickup.item).unwrap().name));
             }
         }
 
@@ -69,7 +69,7 @@ impl<'a> System<'a> for PotionUseSystem {
             if let Some(potion) = potion {
                 stats.hp = i32::min(stats.max_hp, stats.hp + potion.heal_amount);
                 if entity == *player_entity {
-                    gamelog.entries.insert(0, format!(
+                    gamelog.log(LogCategory::Items, format!(
                         "You drink the {}, healing {} hp.",
                         names.get(want_to_drink_potion.potion).unwrap().name, potion.heal_amount
                     ));
@@ -114,7 +114,7 @@ impl<'a> System<'a> for ItemDropSystem {
             backpack_items.remove(to_drop.item);
 
             if entity == *player_entity {
-                gamelog.entries.insert(0, format!("You drop the {}.", names.get(to_drop.item).unwrap().name));
+                gamelog.log(LogCategory::Items, format!("You drop the {}.", names.get(to_drop.item).unwrap().name));
             }
         }
 
@@ -0,0 +1,311 @@
+use rltk::VirtualKeyCode;
+use std::collections::HashMap;
+
+/// Where the player's keybindings are kept, relative to the working directory.
+pub const KEYMAP_FILE: &str = "keybindings.cfg";
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum Command {
+    MoveWest,
+    MoveEast,
+    MoveNorth,
+    MoveSouth,
+    MoveNorthEast,
+    MoveNorthWest,
+    MoveSouthEast,
+    MoveSouthWest,
+    PickUp,
+    Inventory,
+    Drop,
+    Descend,
+    Look,
+    MessageLog,
+    Keybindings,
+    Options,
+    AutoExplore,
+    Wait,
+    Rest,
+    CloseDoor,
+    Search
+}
+
+impl Command {
+    pub const ALL: [Command; 21] = [
+        Command::MoveWest, Command::MoveEast, Command::MoveNorth, Command::MoveSouth,
+        Command::MoveNorthEast, Command::MoveNorthWest, Command::MoveSouthEast, Command::MoveSouthWest,
+        Command::PickUp, Command::Inventory, Command::Drop, Command::Descend,
+        Command::Look, Command::MessageLog, Command::Keybindings, Command::Options,
+        Command::AutoExplore, Command::Wait, Command::Rest, Command::CloseDoor, Command::Search
+    ];
+
+    /// The name used in the keybindings file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::MoveWest => "move_west",
+            Command::MoveEast => "move_east",
+            Command::MoveNorth => "move_north",
+            Command::MoveSouth => "move_south",
+            Command::MoveNorthEast => "move_north_east",
+            Command::MoveNorthWest => "move_north_west",
+            Command::MoveSouthEast => "move_south_east",
+            Command::MoveSouthWest => "move_south_west",
+            Command::PickUp => "pick_up",
+            Command::Inventory => "inventory",
+            Command::Drop => "drop",
+            Command::Descend => "descend",
+            Command::Look => "look",
+            Command::MessageLog => "message_log",
+            Command::Keybindings => "keybindings",
+            Command::Options => "options",
+            Command::AutoExplore => "auto_explore",
+            Command::Wait => "wait",
+            Command::Rest => "rest",
+            Command::CloseDoor => "close_door",
+            Command::Search => "search"
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Command::MoveWest => "Move west",
+            Command::MoveEast => "Move east",
+            Command::MoveNorth => "Move north",
+            Command::MoveSouth => "Move south",
+            Command::MoveNorthEast => "Move north-east",
+            Command::MoveNorthWest => "Move north-west",
+            Command::MoveSouthEast => "Move south-east",
+            Command::MoveSouthWest => "Move south-west",
+            Command::PickUp => "Pick up",
+            Command::Inventory => "Inventory",
+            Command::Drop => "Drop item",
+            Command::Descend => "Go down stairs",
+            Command::Look => "Look around",
+            Command::MessageLog => "Message log",
+            Command::Keybindings => "Keybindings",
+            Command::Options => "Options",
+            Command::AutoExplore => "Explore",
+            Command::Wait => "Wait a turn",
+            Command::Rest => "Rest until healed",
+            Command::CloseDoor => "Close a door",
+            Command::Search => "Search nearby"
+        }
+    }
+
+    /// The step a movement command takes.
+    pub fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Command::MoveWest => Some((-1, 0)),
+            Command::MoveEast => Some((1, 0)),
+            Command::MoveNorth => Some((0, -1)),
+            Command::MoveSouth => Some((0, 1)),
+            Command::MoveNorthEast => Some((1, -1)),
+            Command::MoveNorthWest => Some((-1, -1)),
+            Command::MoveSouthEast => Some((1, 1)),
+            Command::MoveSouthWest => Some((-1, 1)),
+            _ => None
+        }
+    }
+
+    fn default_keys(self) -> &'static [VirtualKeyCode] {
+        use VirtualKeyCode::*;
+        match self {
+            Command::MoveWest => &[Left, Numpad4, H],
+            Command::MoveEast => &[Right, Numpad6, L],
+            Command::MoveNorth => &[Up, Numpad8, K],
+            Command::MoveSouth => &[Down, Numpad2, J],
+            Command::MoveNorthEast => &[Numpad9, Y],
+            Command::MoveNorthWest => &[Numpad7, U],
+            Command::MoveSouthEast => &[Numpad3, N],
+            Command::MoveSouthWest => &[Numpad1, B],
+            Command::PickUp => &[G],
+            Command::Inventory => &[I],
+            Command::Drop => &[D],
+            Command::Descend => &[Period],
+            Command::Look => &[X],
+            Command::MessageLog => &[M],
+            Command::Keybindings => &[F1],
+            Command::Options => &[O],
+            Command::AutoExplore => &[Z],
+            Command::Wait => &[Numpad5, W],
+            Command::Rest => &[R],
+            Command::CloseDoor => &[C],
+            Command::Search => &[S]
+        }
+    }
+}
+
+/// The keys that can be named in the keybindings file. Escape is kept back so
+/// there is always a way out of menus.
+const BINDABLE_KEYS: [VirtualKeyCode; 83] = {
+    use VirtualKeyCode::*;
+    [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+        Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+        Left, Right, Up, Down, Home, End, PageUp, PageDown, Insert, Delete,
+        F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+        Period, Comma, Slash, Semicolon, Apostrophe, Minus, Equals, LBracket, RBracket, Backslash, Grave,
+        Space, Return, Tab, Back
+    ]
+};
+
+pub fn key_name(key: VirtualKeyCode) -> String {
+    format!("{:?}", key)
+}
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    BINDABLE_KEYS.iter().copied().find(|key| key_name(*key).eq_ignore_ascii_case(name))
+}
+
+/// Which command each key stands for while walking around the map.
+pub struct Keymap {
+    bindings: HashMap<VirtualKeyCode, Command>
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut bindings = HashMap::new();
+        for command in Command::ALL.iter() {
+            for key in command.default_keys() {
+                bindings.insert(*key, *command);
+            }
+        }
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    pub fn command_for(&self, key: VirtualKeyCode) -> Option<Command> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// A command's keys, in a stable order.
+    pub fn keys_for(&self, command: Command) -> Vec<VirtualKeyCode> {
+        BINDABLE_KEYS.iter().copied().filter(|key| self.command_for(*key) == Some(command)).collect()
+    }
+
+    pub fn is_bindable(key: VirtualKeyCode) -> bool {
+        BINDABLE_KEYS.contains(&key)
+    }
+
+    /// Binds `key` to `command`, returning the command it was taken away from.
+    pub fn bind(&mut self, key: VirtualKeyCode, command: Command) -> Option<Command> {
+        self.bindings.insert(key, command).filter(|previous| *previous != command)
+    }
+
+    pub fn clear(&mut self, command: Command) {
+        self.bindings.retain(|_, bound| *bound != command);
+    }
+
+    /// Reads `command = Key, Key` lines. Commands the file doesn't mention keep their
+    /// default keys, unless the file has given those keys to something else. Every
+    /// unknown name and every key bound to two commands is reported.
+    pub fn parse(text: &str) -> Result<Keymap, Vec<String>> {
+        let mut bindings: HashMap<VirtualKeyCode, Command> = HashMap::new();
+        let mut mentioned: Vec<Command> = Vec::new();
+        let mut errors = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, keys) = match line.split_once('=') {
+                Some((name, keys)) => (name.trim(), keys),
+                None => {
+                    errors.push(format!("line {}: expected `command = keys`", number + 1));
+                    continue;
+                }
+            };
+            let command = match Command::ALL.iter().find(|command| command.name() == name) {
+                Some(command) => *command,
+                None => {
+                    errors.push(format!("line {}: unknown command `{}`", number + 1, name));
+                    continue;
+                }
+            };
+            mentioned.push(command);
+
+            for key_text in keys.split(',').map(str::trim).filter(|key| !key.is_empty()) {
+                match parse_key(key_text) {
+                    None => errors.push(format!("line {}: unknown key `{}`", number + 1, key_text)),
+                    Some(key) => match bindings.insert(key, command) {
+                        Some(other) if other != command => errors.push(format!(
+                            "line {}: {} is bound to both {} and {}",
+                            number + 1, key_name(key), other.name(), command.name()
+                        )),
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        for command in Command::ALL.iter().filter(|command| !mentioned.contains(command)) {
+            for key in command.default_keys() {
+                bindings.entry(*key).or_insert(*command);
+            }
+        }
+        Ok(Keymap { bindings })
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut text = String::from("# command = key, key, ...\n");
+        for command in Command::ALL.iter() {
+            let keys: Vec<String> = self.keys_for(*command).into_iter().map(key_name).collect();
+            text.push_str(&format!("{} = {}\n", command.name(), keys.join(", ")));
+        }
+        text
+    }
+
+    /// Loads the keybindings file, falling back to the defaults if there isn't one.
+    pub fn load() -> Result<Keymap, Vec<String>> {
+        match std::fs::read_to_string(KEYMAP_FILE) {
+            Ok(text) => Keymap::parse(&text),
+            Err(_) => Ok(Keymap::default())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::write(KEYMAP_FILE, self.to_config())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_survive_a_round_trip_through_the_file() {
+        let defaults = Keymap::default();
+        let parsed = Keymap::parse(&defaults.to_config()).expect("defaults should parse");
+        for key in BINDABLE_KEYS.iter() {
+            assert_eq!(parsed.command_for(*key), defaults.command_for(*key), "{:?}", key);
+        }
+        assert_eq!(defaults.command_for(VirtualKeyCode::H), Some(Command::MoveWest));
+        assert_eq!(defaults.command_for(VirtualKeyCode::Period), Some(Command::Descend));
+    }
+
+    #[test]
+    fn conflicts_and_unknown_names_are_reported() {
+        let errors = Keymap::parse("move_west = A, Left\nlook = a\njump = Space\ndrop = Hyperspace\n")
+            .err().expect("should fail");
+        assert_eq!(errors, vec![
+            "line 2: A is bound to both move_west and look",
+            "line 3: unknown command `jump`",
+            "line 4: unknown key `Hyperspace`"
+        ]);
+    }
+
+    #[test]
+    fn unmentioned_commands_keep_defaults_that_are_still_free() {
+        let keymap = Keymap::parse("# dvorak friendly\nmove_west = D\n").unwrap();
+        assert_eq!(keymap.command_for(VirtualKeyCode::D), Some(Command::MoveWest));
+        assert_eq!(keymap.command_for(VirtualKeyCode::H), None);
+        assert!(keymap.keys_for(Command::Drop).is_empty());
+        assert_eq!(keymap.command_for(VirtualKeyCode::I), Some(Command::Inventory));
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use specs::prelude::*;
+use rltk::{field_of_view, Algorithm2D, Point};
+use crate::game_map::GameMap;
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::components::{LightSource, Fuel, InBackpack, Position, Viewshed};
+use super::RunState;
+
+/// Tiles dimmer than this are too dark to make anything out.
+pub const MIN_LIGHT: f32 = 0.1;
+/// Fuel left when the player is warned that their lantern is running low.
+const LOW_FUEL: i32 = 50;
+
+/// Works out the light map from the ambient light, the torches and the lanterns being
+/// carried, and burns a turn of fuel from the player's lantern on every player turn.
+/// Runs before `VisibilitySystem`, which only shows the player what's lit.
+pub struct LightingSystem;
+
+impl<'a> System<'a> for LightingSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( WriteExpect<'a, GameMap>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, GameLog>,
+                        Entities<'a>,
+                        ReadStorage<'a, LightSource>,
+                        WriteStorage<'a, Fuel>,
+                        ReadStorage<'a, InBackpack>,
+                        ReadStorage<'a, Position>,
+                        WriteStorage<'a, Viewshed> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, player_entity, run_state, mut log, entities, lights, mut fuel, backpack, positions, mut viewsheds) = data;
+
+        // each carrier has one lantern lit at a time; the rest are spares
+        let mut lanterns: HashMap<Entity, Entity> = HashMap::new();
+        for (lantern, _light, fuel, pack) in (&entities, &lights, &fuel, &backpack).join() {
+            if fuel.turns > 0 {
+                lanterns.entry(pack.owner).or_insert(lantern);
+            }
+        }
+
+        if *run_state == RunState::PlayerTurn {
+            if let Some(fuel) = lanterns.get(&*player_entity).and_then(|lantern| fuel.get_mut(*lantern)) {
+                fuel.turns -= 1;
+                if fuel.turns == LOW_FUEL {
+                    LogBuilder::new(LogCategory::General).text("Your lantern is running low on oil.").log(&mut log);
+                } else if fuel.turns == 0 {
+                    LogBuilder::new(LogCategory::General).text("Your lantern sputters out.").major().log(&mut log);
+                }
+            }
+        }
+
+        let mut sources: Vec<(Point, &LightSource)> = (&lights, &positions, !&fuel).join()
+            .map(|(light, pos, _no_fuel)| (Point::new(pos.x, pos.y), light))
+            .collect();
+        for (owner, lantern) in lanterns.iter() {
+            if let (Some(pos), Some(light)) = (positions.get(*owner), lights.get(*lantern)) {
+                if fuel.get(*lantern).is_some_and(|fuel| fuel.turns > 0) {
+                    sources.push((Point::new(pos.x, pos.y), light));
+                }
+            }
+        }
+
+        let mut light = vec![map.ambient_light; map.light.len()];
+        for (origin, source) in sources {
+            for tile in field_of_view(origin, source.range, &*map) {
+                if !map.in_bounds(tile) {
+                    continue;
+                }
+                let distance = rltk::DistanceAlg::Pythagoras.distance2d(origin, tile);
+                let level = source.intensity * (1.0 - distance / (source.range as f32 + 1.0));
+                let idx = map.xy_idx(tile.x, tile.y);
+                light[idx] = f32::max(light[idx], level.clamp(0.0, 1.0));
+            }
+        }
+
+        // the player has to look again when the light changes
+        if light != map.light {
+            map.light = light;
+            if let Some(viewshed) = viewsheds.get_mut(*player_entity) {
+                viewshed.dirty = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spawner;
+    use crate::components::Monster;
+    use crate::visibility_system::VisibilitySystem;
+    use crate::snapshot::{fixture_world, named};
+
+    fn light_at(ecs: &World, x: i32, y: i32) -> f32 {
+        let map = ecs.fetch::<GameMap>();
+        map.light[map.xy_idx(x, y)]
+    }
+
+    fn look(ecs: &World) {
+        LightingSystem.run_now(ecs);
+        VisibilitySystem.run_now(ecs);
+    }
+
+    fn orc_in_view(ecs: &World) -> bool {
+        let map = ecs.fetch::<GameMap>();
+        let monsters = ecs.read_storage::<Monster>();
+        let positions = ecs.read_storage::<Position>();
+        (&monsters, &positions).join().any(|(_, pos)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+    }
+
+    #[test]
+    fn only_lit_tiles_can_be_seen_in_the_dark() {
+        let mut ecs = fixture_world();
+        ecs.fetch_mut::<GameMap>().ambient_light = 0.0;
+        ecs.insert(RunState::MonsterTurn);
+        look(&ecs);
+        assert_eq!(light_at(&ecs, 10, 10), 0.0);
+        assert!(!orc_in_view(&ecs), "the orc is lurking in the dark");
+        {
+            let map = ecs.fetch::<GameMap>();
+            assert!(map.visible_tiles[map.xy_idx(11, 10)], "the player can feel their way around");
+        }
+
+        spawner::spawn_entity(&mut ecs, 14, 10, "Torch");
+        look(&ecs);
+        assert!(light_at(&ecs, 14, 10) > light_at(&ecs, 12, 10));
+        assert!(light_at(&ecs, 12, 10) > MIN_LIGHT);
+        assert_eq!(light_at(&ecs, 30, 10), 0.0);
+        assert!(orc_in_view(&ecs));
+    }
+
+    #[test]
+    fn a_carried_lantern_lights_the_way_until_the_oil_runs_out() {
+        let mut ecs = fixture_world();
+        ecs.fetch_mut::<GameMap>().ambient_light = 0.0;
+        let player = *ecs.fetch::<Entity>();
+        spawner::lantern(&mut ecs, player, 2);
+        let lantern = named(&ecs, "Lantern");
+
+        ecs.insert(RunState::MonsterTurn);
+        look(&ecs);
+        assert!(orc_in_view(&ecs));
+        assert_eq!(ecs.read_storage::<Fuel>().get(lantern).unwrap().turns, 2, "only player turns burn oil");
+
+        ecs.insert(RunState::PlayerTurn);
+        look(&ecs);
+        look(&ecs);
+        assert_eq!(ecs.read_storage::<Fuel>().get(lantern).unwrap().turns, 0);
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].fragments[0].text, "Your lantern sputters out.");
+        assert!(!orc_in_view(&ecs));
+    }
+}
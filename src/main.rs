@@ -1,31 +1,61 @@
 mod rect;
 mod game_map;
+mod clock;
+mod weather;
 mod components;
 mod visibility_system;
+mod lighting_system;
 mod monster_ai_system;
 mod map_ai_system;
+mod memory_system;
+mod door_system;
+mod trap_system;
+mod status_system;
+mod regen_system;
 mod melee_combat_system;
 mod damage_system;
 mod gui;
 mod game_log;
+mod keymap;
+mod options;
+mod tileset;
 mod spawner;
 mod inventory_system;
+mod map_builders;
+mod travel;
+mod frontend;
+mod frame_buffer;
+mod terminal;
+#[cfg(test)]
+mod snapshot;
 
 use rltk::{
-    Point, Console, GameState, Rltk, RGB,
+    Point, GameState, Rltk, RGB,
     VirtualKeyCode, to_cp437, RandomNumberGenerator
 };
 use specs::prelude::*;
-use std::cmp::{max, min};
 use game_map::{GameMap, TileType};
 use components::*;
-use visibility_system::VisibilitySystem;
+use clock::{ClockSystem, GameClock};
+use weather::{WeatherSystem, WeatherState};
+use visibility_system::{VisibilitySystem, SpotChecks};
+use lighting_system::LightingSystem;
 use monster_ai_system::MonsterAI;
 use map_ai_system::MapIndexingSystem;
+use memory_system::EntityMemorySystem;
+use door_system::DoorSystem;
+use trap_system::TrapSystem;
+use status_system::StatusEffectSystem;
+use regen_system::RegenSystem;
 use melee_combat_system::MeleeCombatSystem;
 use damage_system::DamageSystem;
-use game_log::GameLog;
+use game_log::{GameLog, LogBuilder, LogCategory};
 use inventory_system::{ItemCollectionSystem, ItemDropSystem, PotionUseSystem};
+use frontend::Frontend;
+use keymap::{Command, Keymap};
+use options::{Options, Font};
+use tileset::Tileset;
+use travel::{Travel, TravelStep};
 
 #[macro_use]
 extern crate specs_derive;
@@ -37,93 +67,383 @@ pub enum RunState {
     PlayerTurn,
     MonsterTurn,
     ShowInventory,
-    ShowDropItem
+    ShowDropItem,
+    NextLevel,
+    MapGeneration,
+    LookMode,
+    ShowLog,
+    Keybindings,
+    Options
 }
 
+/// What a d20 must reach to find something hidden nearby by searching.
+const SEARCH_DIFFICULTY: i32 = 8;
+
+/// The biggest count that can be typed in front of a command.
+const MAX_COUNT: u32 = 999;
+
+/// How bright a tile in view is drawn when hardly any light reaches it.
+const DARKEST_BRIGHTNESS: f32 = 0.35;
+
+/// Milliseconds each map generation snapshot stays on screen.
+const MAPGEN_FRAME_MS: f32 = 100.0;
+
 pub struct State {
     ecs: World,
+    show_mapgen: bool,
+    /// A map builder asked for with `--builder=<name>`, used for every level instead of
+    /// the roll for its depth.
+    builder_choice: Option<String>,
+    mapgen_history: Vec<GameMap>,
+    mapgen_index: usize,
+    mapgen_timer: f32,
+    mapgen_paused: bool,
+    look_cursor: Point,
+    log_viewer: gui::LogViewer,
+    keybinding_menu: gui::KeybindingMenu,
+    options_menu: gui::OptionsMenu,
+    travel: Option<Travel>,
+    /// A count typed in front of the next command, or 0.
+    count: u32,
 }
 
 impl State {
+    fn new(ecs: World, show_mapgen: bool) -> State {
+        State {
+            ecs,
+            show_mapgen,
+            builder_choice: None,
+            mapgen_history: Vec::new(),
+            mapgen_index: 0,
+            mapgen_timer: 0.0,
+            mapgen_paused: false,
+            look_cursor: Point::new(0, 0),
+            log_viewer: gui::LogViewer::default(),
+            keybinding_menu: gui::KeybindingMenu::default(),
+            options_menu: gui::OptionsMenu::default(),
+            travel: None,
+            count: 0,
+        }
+    }
+
     fn run_systems(&mut self) {
+        let mut clock = ClockSystem;
+        let mut weather = WeatherSystem;
+        let mut doors = DoorSystem;
+        let mut lighting = LightingSystem;
         let mut vis = VisibilitySystem;
         let mut mob = MonsterAI;
         let mut map_indexer = MapIndexingSystem;
+        let mut traps = TrapSystem;
+        let mut status_effects = StatusEffectSystem;
         let mut melee = MeleeCombatSystem;
         let mut damage_system = DamageSystem;
         let mut item_collection_system = ItemCollectionSystem;
         let mut item_drop_system = ItemDropSystem;
         let mut potion_use_system = PotionUseSystem;
+        let mut regen = RegenSystem;
+        let mut memory = EntityMemorySystem;
 
+        clock.run_now(&self.ecs);
+        weather.run_now(&self.ecs);
+        doors.run_now(&self.ecs);
+        lighting.run_now(&self.ecs);
         vis.run_now(&self.ecs);
         mob.run_now(&self.ecs);
         map_indexer.run_now(&self.ecs);
+        traps.run_now(&self.ecs);
+        status_effects.run_now(&self.ecs);
         melee.run_now(&self.ecs);
         damage_system.run_now(&self.ecs);
         item_collection_system.run_now(&self.ecs);
         item_drop_system.run_now(&self.ecs);
         potion_use_system.run_now(&self.ecs);
+        regen.run_now(&self.ecs);
+        memory.run_now(&self.ecs);
 
         self.ecs.maintain();
     }
 
-    fn state_after_player_input(self: &mut State, ctx: &mut Rltk) -> RunState {
-        if let Some(key) = ctx.key {
-            match key {
-                VirtualKeyCode::Left |
-                VirtualKeyCode::Numpad4 |
-                VirtualKeyCode::H => try_move_player(&mut self.ecs, -1, 0),
+    fn state_after_player_input(self: &mut State, ctx: &mut dyn Frontend) -> RunState {
+        if ctx.left_click() {
+            let (mouse_x, mouse_y) = ctx.mouse_pos();
+            let on_map = {
+                let map = self.ecs.fetch::<GameMap>();
+                mouse_x >= 0 && mouse_x < map.width && mouse_y >= 0 && mouse_y < map.height
+            };
+            if on_map {
+                self.start_travel(Travel::to(&self.ecs, Point::new(mouse_x, mouse_y)));
+            }
+            return RunState::AwaitingInput;
+        }
+        let command = ctx.key().and_then(|key| self.ecs.fetch::<Keymap>().command_for(key));
+        if command.is_none() {
+            if let Some(digit) = ctx.key().and_then(count_digit) {
+                self.count = u32::min(self.count * 10 + digit, MAX_COUNT);
+                return RunState::AwaitingInput;
+            }
+        }
+        let count = if ctx.key().is_some() { std::mem::take(&mut self.count) } else { 0 };
 
-                VirtualKeyCode::Right |
-                VirtualKeyCode::Numpad6 |
-                VirtualKeyCode::L => try_move_player(&mut self.ecs, 1, 0),
+        if let Some(command) = command {
+            if let Some((delta_x, delta_y)) = command.direction() {
+                if ctx.shift() {
+                    self.travel = Some(Travel::run(&self.ecs, (delta_x, delta_y)));
+                    return RunState::AwaitingInput;
+                }
+                if count > 1 {
+                    self.travel = Some(Travel::repeat(&self.ecs, (delta_x, delta_y), count));
+                    return RunState::AwaitingInput;
+                }
+                try_move_player(&mut self.ecs, delta_x, delta_y);
+                return RunState::PlayerTurn;
+            }
+            match command {
+                Command::PickUp => get_item(&mut self.ecs),
 
-                VirtualKeyCode::Up |
-                VirtualKeyCode::Numpad8 |
-                VirtualKeyCode::K => try_move_player(&mut self.ecs, 0, -1),
+                Command::Descend => {
+                    if try_next_level(&mut self.ecs) {
+                        return RunState::NextLevel;
+                    }
+                },
 
-                VirtualKeyCode::Down |
-                VirtualKeyCode::Numpad2 |
-                VirtualKeyCode::J => try_move_player(&mut self.ecs, 0, 1),
+                Command::Inventory => return RunState::ShowInventory,
+                Command::Drop => return RunState::ShowDropItem,
+                Command::MessageLog => {
+                    self.log_viewer.scroll = 0;
+                    return RunState::ShowLog;
+                },
+                Command::Look => {
+                    self.look_cursor = *self.ecs.fetch::<Point>();
+                    return RunState::LookMode;
+                },
+                Command::Keybindings => {
+                    self.keybinding_menu = gui::KeybindingMenu::default();
+                    return RunState::Keybindings;
+                },
+                Command::Options => {
+                    self.options_menu = gui::OptionsMenu::default();
+                    return RunState::Options;
+                },
+                Command::AutoExplore => {
+                    self.start_travel(Travel::explore(&self.ecs));
+                    return RunState::AwaitingInput;
+                },
+                Command::Wait => {
+                    if count > 1 {
+                        self.travel = Some(Travel::repeat(&self.ecs, (0, 0), count));
+                        return RunState::AwaitingInput;
+                    }
+                    return RunState::PlayerTurn;
+                },
+                Command::Rest => {
+                    self.start_travel(Travel::rest(&self.ecs));
+                    return RunState::AwaitingInput;
+                },
+                Command::CloseDoor => {
+                    if !close_door(&mut self.ecs) {
+                        return RunState::AwaitingInput;
+                    }
+                },
+                Command::Search => search(&mut self.ecs),
 
-                // Diagonals
-                VirtualKeyCode::Numpad9 |
-                VirtualKeyCode::Y => try_move_player(&mut self.ecs, 1, -1),
+                _ => { return RunState::AwaitingInput }
+            }
+            RunState::PlayerTurn
+        } else {
+            RunState::AwaitingInput
+        }
+    }
 
-                VirtualKeyCode::Numpad7 |
-                VirtualKeyCode::U => try_move_player(&mut self.ecs, -1, -1),
+    fn start_travel(&mut self, travel: Result<Travel, String>) {
+        match travel {
+            Ok(travel) => self.travel = Some(travel),
+            Err(reason) => self.ecs.fetch_mut::<GameLog>().log(LogCategory::General, reason)
+        }
+    }
 
-                VirtualKeyCode::Numpad3 |
-                VirtualKeyCode::N => try_move_player(&mut self.ecs, 1, 1),
+    /// Takes the next step of a walk or rest, as a normal player turn. Any key or click stops it.
+    fn continue_travel(&mut self, mut travel: Travel, ctx: &mut dyn Frontend) -> RunState {
+        if ctx.key().is_some() || ctx.left_click() {
+            return RunState::AwaitingInput;
+        }
+        match travel.next_step(&self.ecs) {
+            TravelStep::Move(delta_x, delta_y) => {
+                try_move_player(&mut self.ecs, delta_x, delta_y);
+                self.travel = Some(travel);
+                RunState::PlayerTurn
+            },
+            TravelStep::Wait => {
+                self.travel = Some(travel);
+                RunState::PlayerTurn
+            },
+            TravelStep::Arrived => RunState::AwaitingInput,
+            TravelStep::Interrupted(reason) => {
+                self.ecs.fetch_mut::<GameLog>().log(LogCategory::General, reason);
+                RunState::AwaitingInput
+            }
+        }
+    }
 
-                VirtualKeyCode::Numpad1 |
-                VirtualKeyCode::B => try_move_player(&mut self.ecs, -1, 1),
+    fn generate_world_map(&mut self, depth: i32) {
+        let mut builder;
+        {
+            let mut rng = self.ecs.write_resource::<RandomNumberGenerator>();
+            builder = match self.builder_choice.as_deref().and_then(|name| map_builders::named_builder(name, depth)) {
+                Some(chosen) => chosen,
+                None => map_builders::random_builder(depth, &mut rng)
+            };
+            builder.build_map(&mut rng);
+        }
+        if self.show_mapgen {
+            self.mapgen_history = builder.get_snapshot_history();
+            self.mapgen_index = 0;
+            self.mapgen_timer = 0.0;
+            self.mapgen_paused = false;
+        }
+        {
+            let mut map = self.ecs.write_resource::<GameMap>();
+            *map = builder.get_map();
+        }
+        *self.ecs.write_resource::<SpotChecks>() = SpotChecks::default();
+        builder.spawn_entities(&mut self.ecs);
 
-                VirtualKeyCode::G => get_item(&mut self.ecs),
+        let player_start = builder.get_starting_position();
+        let mut player_position = self.ecs.write_resource::<Point>();
+        *player_position = Point::new(player_start.x, player_start.y);
 
-                VirtualKeyCode::I => return RunState::ShowInventory,
-                VirtualKeyCode::D => return RunState::ShowDropItem,
+        let player_entity = self.ecs.fetch::<Entity>();
+        let mut positions = self.ecs.write_storage::<Position>();
+        if let Some(pos) = positions.get_mut(*player_entity) {
+            *pos = player_start;
+        }
 
-                _ => { return RunState::AwaitingInput }
+        let mut viewsheds = self.ecs.write_storage::<Viewshed>();
+        if let Some(viewshed) = viewsheds.get_mut(*player_entity) {
+            viewshed.dirty = true;
+        }
+    }
+
+    /// Every hour someone new turns up on the street, out of the player's sight, unless
+    /// it's crowded enough already.
+    fn spawn_wanderer_on_the_hour(&mut self) {
+        let ground: Vec<usize> = {
+            let map = self.ecs.fetch::<GameMap>();
+            if !map.outdoors || !self.ecs.fetch::<GameClock>().is_on_the_hour() || spawner::street_is_full(&self.ecs) {
+                return;
+            }
+            (0..map.tiles.len())
+                .filter(|idx| matches!(map.tiles[*idx], TileType::Sand | TileType::Road) && !map.blocked[*idx] && !map.visible_tiles[*idx])
+                .collect()
+        };
+        spawner::spawn_wanderer(&mut self.ecs, &ground);
+    }
+
+    /// Everything that isn't the player or in the player's backpack stays behind.
+    fn entities_to_remove_on_level_change(&self) -> Vec<Entity> {
+        let entities = self.ecs.entities();
+        let player_entity = self.ecs.fetch::<Entity>();
+        let backpack = self.ecs.read_storage::<InBackpack>();
+
+        entities.join()
+            .filter(|entity| *entity != *player_entity)
+            .filter(|entity| backpack.get(*entity).is_none_or(|pack| pack.owner != *player_entity))
+            .collect()
+    }
+
+    fn goto_next_level(&mut self) {
+        for target in self.entities_to_remove_on_level_change() {
+            self.ecs.delete_entity(target).expect("Unable to delete entity on level change");
+        }
+
+        let current_depth = self.ecs.fetch::<GameMap>().depth;
+        self.generate_world_map(current_depth + 1);
+
+        let mut gamelog = self.ecs.fetch_mut::<GameLog>();
+        LogBuilder::new(LogCategory::General).text("You climb down to the next level.").major().log(&mut gamelog);
+    }
+
+    /// Plays back the snapshots of the last generated map. Space pauses, the right
+    /// arrow or period steps while paused, and escape skips to the game.
+    fn play_map_generation(&mut self, ctx: &mut dyn Frontend) -> RunState {
+        match ctx.key() {
+            Some(VirtualKeyCode::Space) => self.mapgen_paused = !self.mapgen_paused,
+            Some(VirtualKeyCode::Right) | Some(VirtualKeyCode::Period) if self.mapgen_paused => {
+                self.mapgen_index += 1;
+            },
+            Some(VirtualKeyCode::Escape) | Some(VirtualKeyCode::Return) => {
+                self.mapgen_index = self.mapgen_history.len();
+            },
+            _ => {}
+        }
+
+        if !self.mapgen_paused {
+            self.mapgen_timer += ctx.frame_time_ms();
+            if self.mapgen_timer > MAPGEN_FRAME_MS {
+                self.mapgen_timer = 0.0;
+                self.mapgen_index += 1;
             }
-            RunState::PlayerTurn
-        } else {
-            RunState::AwaitingInput
         }
+
+        if self.mapgen_index >= self.mapgen_history.len() {
+            self.mapgen_history.clear();
+            return RunState::PreRun;
+        }
+
+        draw_map(&self.mapgen_history[self.mapgen_index], &self.ecs.fetch::<Tileset>(), ctx);
+        ctx.print_color(1, 44, RGB::named(rltk::WHITE), RGB::named(rltk::BLACK),
+            &format!("Generating map: step {} of {}{}", self.mapgen_index + 1, self.mapgen_history.len(),
+                if self.mapgen_paused { " (paused)" } else { "" }));
+        ctx.print_color(1, 46, RGB::named(rltk::GREY), RGB::named(rltk::BLACK),
+            "SPACE: pause/resume   RIGHT: step   ESC: skip");
+        RunState::MapGeneration
     }
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
+        ctx.post_scanlines = self.ecs.fetch::<Options>().scanlines;
+        self.frame(ctx);
+    }
+}
+
+impl State {
+    /// Runs one frame of the game against any frontend.
+    fn frame(&mut self, ctx: &mut dyn Frontend) {
         ctx.cls();
+        if *self.ecs.fetch::<RunState>() == RunState::MapGeneration {
+            let run_state = self.play_map_generation(ctx);
+            *self.ecs.write_resource::<RunState>() = run_state;
+            return;
+        }
         {
             let map = self.ecs.fetch::<GameMap>();
+            let tileset = self.ecs.fetch::<Tileset>();
+            draw_map(&map, &tileset, ctx);
+            weather::draw_particles(&map, &self.ecs.fetch::<WeatherState>(), &self.ecs.fetch::<GameClock>(), ctx);
 
-            // draw box
-            gui::draw_ui(&self.ecs, ctx);
+            // then anything standing in view
+            let positions = self.ecs.read_storage::<Position>();
+            let renderables = self.ecs.read_storage::<Renderable>();
+            let names = self.ecs.read_storage::<Name>();
+            let hidden = self.ecs.read_storage::<Hidden>();
+            let mut in_view: Vec<(&Position, &Renderable, Option<&Name>)> = (&positions, &renderables, names.maybe(), !&hidden).join()
+                .map(|(pos, render, name, _not_hidden)| (pos, render, name))
+                .filter(|(pos, _, _)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+                .collect();
+            // the lowest render order ends up on top
+            in_view.sort_by_key(|(_, render, _)| -render.render_order);
+            for (pos, render, name) in in_view {
+                match name.and_then(|name| tileset.entity_sprite(&name.name)) {
+                    Some(sprite) => ctx.set_sprite(pos.x, pos.y, RGB::named(rltk::WHITE), sprite),
+                    None => ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph)
+                }
+            }
 
-            // first render map
-            draw_map(&map, ctx);
+            gui::draw_ui(&self.ecs, ctx);
+            if self.count > 0 {
+                ctx.print_color(66, 43, RGB::named(rltk::YELLOW), RGB::named(rltk::BLACK), &format!(" Count: {} ", self.count));
+            }
         }
 
         let mut run_state;
@@ -134,10 +454,14 @@ impl GameState for State {
                 run_state = RunState::AwaitingInput;
             },
             RunState::AwaitingInput => {
-                run_state = self.state_after_player_input(ctx);
+                run_state = match self.travel.take() {
+                    Some(travel) => self.continue_travel(travel, ctx),
+                    None => self.state_after_player_input(ctx)
+                };
             },
             RunState::PlayerTurn => {
                 self.run_systems();
+                self.spawn_wanderer_on_the_hour();
                 run_state = RunState::MonsterTurn;
             },
             RunState::MonsterTurn => {
@@ -167,7 +491,54 @@ impl GameState for State {
                         run_state = RunState::PlayerTurn;
                     }
                 }
-            }
+            },
+            RunState::NextLevel => {
+                self.goto_next_level();
+                run_state = if self.show_mapgen { RunState::MapGeneration } else { RunState::PreRun };
+            },
+            RunState::LookMode => {
+                match gui::look_mode(&self.ecs, ctx, &mut self.look_cursor) {
+                    gui::LookResult::Looking => {},
+                    gui::LookResult::Done => run_state = RunState::AwaitingInput,
+                    gui::LookResult::Travel(target) => {
+                        self.start_travel(Travel::to(&self.ecs, target));
+                        run_state = RunState::AwaitingInput;
+                    }
+                }
+            },
+            RunState::ShowLog => {
+                if gui::show_log(&self.ecs, ctx, &mut self.log_viewer) == gui::LogViewerResult::Closed {
+                    run_state = RunState::AwaitingInput;
+                }
+            },
+            RunState::Keybindings => {
+                if gui::keybindings_menu(&mut self.ecs, ctx, &mut self.keybinding_menu) == gui::KeybindingMenuResult::Closed {
+                    let saved = self.ecs.fetch::<Keymap>().save();
+                    if let Err(error) = saved {
+                        let mut gamelog = self.ecs.fetch_mut::<GameLog>();
+                        LogBuilder::new(LogCategory::General)
+                            .text(format!("Unable to save {}: {}", keymap::KEYMAP_FILE, error))
+                            .major()
+                            .log(&mut gamelog);
+                    }
+                    run_state = RunState::AwaitingInput;
+                }
+            },
+            RunState::Options => {
+                if gui::options_menu(&mut self.ecs, ctx, &mut self.options_menu) == gui::OptionsMenuResult::Closed {
+                    let options = self.ecs.fetch::<Options>().clone();
+                    let mut gamelog = self.ecs.fetch_mut::<GameLog>();
+                    gamelog.verbosity = options.verbosity;
+                    if let Err(error) = options.save() {
+                        LogBuilder::new(LogCategory::General)
+                            .text(format!("Unable to save {}: {}", options::OPTIONS_FILE, error))
+                            .major()
+                            .log(&mut gamelog);
+                    }
+                    run_state = RunState::AwaitingInput;
+                }
+            },
+            RunState::MapGeneration => {}
         }
 
         {
@@ -175,19 +546,16 @@ impl GameState for State {
             *run_writer = run_state;
         }
         damage_system::delete_the_dead(&mut self.ecs);
+    }
+}
 
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
 
-        // render anything else that can be rendered
-        let map = self.ecs.fetch::<GameMap>();
-        for (pos, render) in (&positions, &renderables).join() {
-            let idx = map.xy_idx(pos.x, pos.y);
-            if map.visible_tiles[idx] {
-                ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph);
-            }
-        }
-    }
+/// The number a key adds to a count, for the digit keys along the top of the keyboard.
+fn count_digit(key: VirtualKeyCode) -> Option<u32> {
+    use VirtualKeyCode::*;
+    [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9].iter()
+        .position(|digit| *digit == key)
+        .map(|digit| digit as u32)
 }
 
 
@@ -197,13 +565,17 @@ fn try_move_player(ecs: &mut World, delta_x: i32, delta_y: i32) {
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
     let entities = ecs.entities();
     let combat_stats = ecs.read_storage::<CombatStats>();
+    let doors = ecs.read_storage::<Door>();
+    let mut wants_to_open = ecs.write_storage::<WantsToOpenDoor>();
+    let immobilized = ecs.read_storage::<Immobilized>();
+    let mut moved = ecs.write_storage::<EntityMoved>();
     let players = ecs.read_storage::<Player>();
     let map = ecs.fetch::<GameMap>();
 
     for (entity, _player, pos, viewshed) in (&entities, &players, &mut positions, &mut viewsheds).join() {
         let new_pos = Position {
-            x: min(79, max(0, pos.x + delta_x)),
-            y: min(49, max(0, pos.y + delta_y))
+            x: (pos.x + delta_x).clamp(0, 79),
+            y: (pos.y + delta_y).clamp(0, 49)
         };
 
         let destination_idx = map.xy_idx(new_pos.x, new_pos.y);
@@ -217,9 +589,22 @@ fn try_move_player(ecs: &mut World, delta_x: i32, delta_y: i32) {
             }
         }
 
+        let closed_door = map.tile_content[destination_idx].iter()
+            .find(|potential_door| doors.get(**potential_door).is_some_and(|door| !door.open));
+        if let Some(door) = closed_door {
+            wants_to_open.insert(entity, WantsToOpenDoor { door: *door }).expect("Unable to insert want to open door");
+            return;
+        }
+
+        if immobilized.contains(entity) {
+            ecs.fetch_mut::<GameLog>().log(LogCategory::General, "You are caught fast and can't move.");
+            return;
+        }
+
         if !map.blocked[destination_idx] {
             *pos = new_pos;
             viewshed.dirty = true;
+            moved.insert(entity, EntityMoved).expect("Unable to insert marker");
 
             let mut ppos = ecs.write_resource::<Point>();
             ppos.x = pos.x;
@@ -229,6 +614,110 @@ fn try_move_player(ecs: &mut World, delta_x: i32, delta_y: i32) {
 }
 
 
+fn try_next_level(ecs: &mut World) -> bool {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<GameMap>();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+
+    if map.tiles[player_idx] == TileType::DownStairs {
+        true
+    } else {
+        let mut gamelog = ecs.fetch_mut::<GameLog>();
+        gamelog.log(LogCategory::General, "There is no way down from here.");
+        false
+    }
+}
+
+
+/// Shuts an open door next to the player, as long as nothing is standing or lying in it.
+fn close_door(ecs: &mut World) -> bool {
+    let player_pos = *ecs.fetch::<Point>();
+    let player_entity = *ecs.fetch::<Entity>();
+    let map = ecs.fetch::<GameMap>();
+    let doors = ecs.read_storage::<Door>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+
+    let mut blocked = false;
+    for delta_y in -1..=1 {
+        for delta_x in -1..=1 {
+            let (x, y) = (player_pos.x + delta_x, player_pos.y + delta_y);
+            if (delta_x, delta_y) == (0, 0) || x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+            let content = &map.tile_content[map.xy_idx(x, y)];
+            let open_door = content.iter().find(|entity| doors.get(**entity).is_some_and(|door| door.open));
+            if let Some(door) = open_door {
+                if content.len() > 1 {
+                    blocked = true;
+                    continue;
+                }
+                ecs.write_storage::<WantsToCloseDoor>()
+                    .insert(player_entity, WantsToCloseDoor { door: *door })
+                    .expect("Unable to insert want to close door");
+                return true;
+            }
+        }
+    }
+
+    if blocked {
+        gamelog.log(LogCategory::General, "Something is in the way.");
+    } else {
+        gamelog.log(LogCategory::General, "There is no open door next to you.");
+    }
+    false
+}
+
+
+/// Takes a turn to look closely around the player for anything hidden.
+fn search(ecs: &mut World) {
+    let player_pos = *ecs.fetch::<Point>();
+    let mut map = ecs.fetch_mut::<GameMap>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let names = ecs.read_storage::<Name>();
+    let mut hidden = ecs.write_storage::<Hidden>();
+    let mut rng = ecs.fetch_mut::<RandomNumberGenerator>();
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+
+    let mut found = Vec::new();
+    for (entity, _hidden, pos) in (&entities, &hidden, &positions).join() {
+        let nearby = rltk::DistanceAlg::Pythagoras.distance2d(player_pos, Point::new(pos.x, pos.y)) < 2.5;
+        if nearby && map.visible_tiles[map.xy_idx(pos.x, pos.y)] && rng.roll_dice(1, 20) >= SEARCH_DIFFICULTY {
+            found.push(entity);
+        }
+    }
+
+    let mut passages = Vec::new();
+    for y in player_pos.y - 1 ..= player_pos.y + 1 {
+        for x in player_pos.x - 1 ..= player_pos.x + 1 {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+            let idx = map.xy_idx(x, y);
+            if map.secret_doors[idx] && rng.roll_dice(1, 20) >= SEARCH_DIFFICULTY {
+                passages.push(idx);
+            }
+        }
+    }
+    if !passages.is_empty() {
+        for idx in passages {
+            map.reveal_secret_door(idx);
+        }
+        gamelog.log(LogCategory::General, "You find a secret passage.");
+        if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(*ecs.fetch::<Entity>()) {
+            viewshed.dirty = true;
+        }
+    } else if found.is_empty() {
+        gamelog.log(LogCategory::General, "You search, but find nothing.");
+    }
+    for entity in found {
+        hidden.remove(entity);
+        let name = names.get(entity).map_or("something", |name| name.name.as_str());
+        gamelog.log(LogCategory::General, format!("You find a {}.", name));
+    }
+}
+
+
 fn get_item(ecs: &mut World) {
     let player_pos = ecs.fetch::<Point>();
     let player_entity = ecs.fetch::<Entity>();
@@ -245,7 +734,7 @@ fn get_item(ecs: &mut World) {
     }
 
     match target_item {
-        None => gamelog.entries.insert(0, "There is nothing here to pick up.".to_string()),
+        None => gamelog.log(LogCategory::Items, "There is nothing here to pick up."),
         Some(item) => {
             let mut pickup = ecs.write_storage::<WantsToPickupItem>();
             pickup.insert(*player_entity, WantsToPickupItem { collected_by: *player_entity, item })
@@ -255,12 +744,15 @@ fn get_item(ecs: &mut World) {
 }
 
 
-pub fn draw_map(map: &GameMap, ctx: &mut Rltk) {
+/// Tiles with a sprite in the tileset are drawn with it. Whatever is remembered on a
+/// tile out of sight is still drawn as a glyph on top. Tiles in view are dimmer the
+/// less light falls on them.
+pub fn draw_map(map: &GameMap, tileset: &Tileset, ctx: &mut dyn Frontend) {
     let mut y = 0;
     let mut x = 0;
     for (idx, tile) in map.tiles.iter().enumerate() {
         if map.revealed_tiles[idx] {
-            let glyph;
+            let mut glyph;
             let mut fg;
             match tile {
                 TileType::Floor => {
@@ -270,13 +762,46 @@ pub fn draw_map(map: &GameMap, ctx: &mut Rltk) {
                 TileType::Wall => {
                     fg = RGB::from_f32(0.0, 1.0, 0.0);
                     glyph = to_cp437('$');
+                },
+                TileType::DownStairs => {
+                    fg = RGB::from_f32(0.0, 1.0, 1.0);
+                    glyph = to_cp437('>');
+                },
+                TileType::Sand => {
+                    fg = RGB::from_f32(0.76, 0.6, 0.33);
+                    glyph = to_cp437('.');
+                },
+                TileType::Road => {
+                    fg = RGB::from_f32(0.55, 0.4, 0.25);
+                    glyph = to_cp437('░');
+                },
+                TileType::WoodFloor => {
+                    fg = RGB::from_f32(0.6, 0.4, 0.2);
+                    glyph = to_cp437('_');
                 }
             }
 
+            let brightness = DARKEST_BRIGHTNESS + (1.0 - DARKEST_BRIGHTNESS) * map.light[idx];
+            let sprite = tileset.tile_sprite(*tile);
+            if let Some(sprite) = sprite {
+                let tint = if map.visible_tiles[idx] { RGB::named(rltk::WHITE) * brightness } else { RGB::named(rltk::GREY) };
+                ctx.set_sprite(x, y, tint, sprite);
+            }
+
+            let mut remembered = false;
             if !map.visible_tiles[idx] {
+                if let Some((remembered_glyph, remembered_fg)) = map.remembered_glyphs[idx] {
+                    glyph = remembered_glyph;
+                    fg = remembered_fg;
+                    remembered = true;
+                }
                 fg = fg.to_greyscale();
+            } else {
+                fg = fg * brightness;
+            }
+            if sprite.is_none() || remembered {
+                ctx.set(x, y, fg, RGB::from_f32(0.0, 0.0, 0.0), glyph);
             }
-            ctx.set(x, y, fg, RGB::from_f32(0.0, 0.0, 0.0), glyph);
         }
 
         x += 1;
@@ -288,51 +813,213 @@ pub fn draw_map(map: &GameMap, ctx: &mut Rltk) {
 }
 
 
+fn register_components(ecs: &mut World) {
+    ecs.register::<Position>();
+    ecs.register::<Renderable>();
+    ecs.register::<Monster>();
+    ecs.register::<Player>();
+    ecs.register::<Name>();
+    ecs.register::<Viewshed>();
+    ecs.register::<BlocksTile>();
+    ecs.register::<CombatStats>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<SufferDamage>();
+    ecs.register::<Item>();
+    ecs.register::<Potion>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<WantsToDropItem>();
+    ecs.register::<WantsToDrinkPotion>();
+    ecs.register::<InBackpack>();
+    ecs.register::<HealthRegen>();
+    ecs.register::<BlocksVisibility>();
+    ecs.register::<Door>();
+    ecs.register::<Locked>();
+    ecs.register::<Key>();
+    ecs.register::<WantsToOpenDoor>();
+    ecs.register::<WantsToCloseDoor>();
+    ecs.register::<Hidden>();
+    ecs.register::<Trap>();
+    ecs.register::<EntityMoved>();
+    ecs.register::<Immobilized>();
+    ecs.register::<Poisoned>();
+    ecs.register::<LightSource>();
+    ecs.register::<Fuel>();
+    ecs.register::<Tracking>();
+    ecs.register::<Wanderer>();
+}
+
 fn main() {
-    use rltk::RltkBuilder;
+    let show_mapgen = std::env::args().any(|arg| arg == "--mapgen");
+    let in_terminal = std::env::args().any(|arg| arg == "--terminal");
+    let mut gs = State::new(World::new(), show_mapgen);
+    gs.builder_choice = std::env::args().find_map(|arg| arg.strip_prefix("--builder=").map(str::to_string));
 
-    let mut context = RltkBuilder::simple80x50()
-        .with_title("Hello bitches")
-        .build();
-    context.with_post_scanlines(true);
+    register_components(&mut gs.ecs);
 
-    let mut gs = State {
-        ecs: World::new(),
+    gs.ecs.insert(if show_mapgen { RunState::MapGeneration } else { RunState::PreRun });
+    let (options, option_errors) = Options::load();
+    let mut gamelog = GameLog::new();
+    gamelog.verbosity = options.verbosity;
+    LogBuilder::new(LogCategory::General).text("Welcome to the Wild Wild West").major().log(&mut gamelog);
+    for error in option_errors {
+        LogBuilder::new(LogCategory::General).text(format!("{}: {}", options::OPTIONS_FILE, error)).major().log(&mut gamelog);
+    }
+    if let Some(name) = gs.builder_choice.as_deref().filter(|name| map_builders::named_builder(name, 1).is_none()) {
+        LogBuilder::new(LogCategory::General).text(format!("--builder: there is no map builder called {}", name)).major().log(&mut gamelog);
+    }
+    match Keymap::load() {
+        Ok(keymap) => gs.ecs.insert(keymap),
+        Err(errors) => {
+            for error in errors {
+                LogBuilder::new(LogCategory::General).text(format!("{}: {}", keymap::KEYMAP_FILE, error)).major().log(&mut gamelog);
+            }
+            LogBuilder::new(LogCategory::General).text("Using the default keybindings.").major().log(&mut gamelog);
+            gs.ecs.insert(Keymap::default());
+        }
+    }
+    let tileset = if options.tiles && !in_terminal {
+        match Tileset::load() {
+            Ok(tileset) => tileset,
+            Err(errors) => {
+                for error in errors {
+                    LogBuilder::new(LogCategory::General).text(format!("{}: {}", tileset::TILESET_MAPPING_FILE, error)).major().log(&mut gamelog);
+                }
+                LogBuilder::new(LogCategory::General).text("Drawing the map with glyphs instead.").major().log(&mut gamelog);
+                Tileset::default()
+            }
+        }
+    } else {
+        Tileset::default()
     };
-
-    let mut map = GameMap::new();
-    map.populate_with_random_rooms();
-    let (player_x, player_y) = map.rooms[0].center();
-
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.register::<SufferDamage>();
-    gs.ecs.register::<Item>();
-    gs.ecs.register::<Potion>();
-    gs.ecs.register::<WantsToPickupItem>();
-    gs.ecs.register::<WantsToDropItem>();
-    gs.ecs.register::<WantsToDrinkPotion>();
-    gs.ecs.register::<InBackpack>();
-
-    gs.ecs.insert(Point::new(player_x, player_y));
-    gs.ecs.insert(RunState::PreRun);
-    gs.ecs.insert(GameLog { entries: vec!["Welcome to the Wild Wild West".to_string()]});
+    let tileset_image = tileset.image.clone();
+    gs.ecs.insert(tileset);
+    gs.ecs.insert(gamelog);
+    gs.ecs.insert(options.clone());
     gs.ecs.insert(RandomNumberGenerator::new());
+    gs.ecs.insert(GameClock::default());
+    gs.ecs.insert(WeatherState::default());
+    gs.ecs.insert(SpotChecks::default());
+
+    gs.ecs.insert(GameMap::new(1));
+    gs.ecs.insert(Point::new(0, 0));
+    let player_entity = spawner::player(&mut gs.ecs, 0, 0);
+    gs.ecs.insert(player_entity);
+    spawner::starting_lantern(&mut gs.ecs, player_entity);
+
+    gs.generate_world_map(1);
 
-    for room in map.rooms.iter().skip(1) {
-        spawner::spawn_room(&mut gs.ecs, room);
+    if in_terminal {
+        terminal::main_loop(80, 50, |ctx| gs.frame(ctx));
+    } else {
+        use rltk::RltkBuilder;
+
+        let (font, font_height) = match options.font {
+            Font::Terminal8x8 => ("terminal8x8.png", 8),
+            Font::Vga8x16 => ("vga8x16.png", 16)
+        };
+        let builder = if tileset_image.is_empty() {
+            match options.font {
+                Font::Terminal8x8 => RltkBuilder::simple80x50(),
+                Font::Vga8x16 => RltkBuilder::vga80x50()
+            }
+        } else {
+            RltkBuilder::new()
+                .with_dimensions(80, 50)
+                .with_tile_dimensions(8, font_height)
+                .with_resource_path(tileset::RESOURCE_PATH)
+                .with_font(&tileset_image, 16, 16)
+                .with_font(font, 8, font_height)
+                .with_simple_console(80, 50, &tileset_image)
+                .with_sparse_console(80, 50, font)
+        };
+        let mut context = builder
+            .with_title(&options.title)
+            .build();
+        context.with_post_scanlines(options.scanlines);
+        rltk::main_loop(context, gs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame_buffer::FrameBuffer;
+    use crate::snapshot::{assert_snapshot, fixture_world, frame_to_text};
+
+    #[test]
+    fn map_matches_snapshot() {
+        let ecs = fixture_world();
+        let mut frame = FrameBuffer::new(80, 50);
+        draw_map(&ecs.fetch::<GameMap>(), &ecs.fetch::<Tileset>(), &mut frame);
+        assert_snapshot("render_map", &frame_to_text(&frame));
+        assert!(frame.sprites.iter().all(Option::is_none));
     }
-    let player_entity = spawner::player(&mut gs.ecs, player_x, player_y);
 
-    gs.ecs.insert(player_entity);
-    gs.ecs.insert(map);
+    #[test]
+    fn tileset_sprites_replace_glyphs_where_they_are_defined() {
+        let ecs = fixture_world();
+        let mut map = GameMap::clone(&ecs.fetch::<GameMap>());
+        let remembered_idx = map.xy_idx(25, 10);
+        map.remembered_glyphs[remembered_idx] = Some((to_cp437('o'), RGB::named(rltk::RED)));
+        let tileset = Tileset::parse("image = example_tiles.jpg\ntile.wall = 0\ntile.floor = 1\n").unwrap();
+        let mut frame = FrameBuffer::new(80, 50);
+        draw_map(&map, &tileset, &mut frame);
+
+        // in sight, out of sight, the stairs with no sprite, and a remembered orc
+        assert_eq!(frame.sprites[map.xy_idx(10, 10)], Some((1, RGB::named(rltk::WHITE))));
+        assert_eq!(frame.get(10, 10).glyph, 32);
+        assert_eq!(frame.sprites[map.xy_idx(25, 9)], Some((0, RGB::named(rltk::GREY))));
+        assert_eq!(frame.sprites[map.xy_idx(18, 13)], None);
+        assert_eq!(frame.get(18, 13).glyph, to_cp437('>'));
+        assert_eq!(frame.sprites[remembered_idx], Some((1, RGB::named(rltk::GREY))));
+        assert_eq!(frame.get(25, 10).glyph, to_cp437('o'));
+    }
 
-    rltk::main_loop(context, gs);
+    #[test]
+    fn searching_next_to_a_secret_passage_opens_it_up() {
+        let mut ecs = fixture_world();
+        let secret = ecs.fetch::<GameMap>().xy_idx(4, 10);
+        ecs.fetch_mut::<GameMap>().secret_doors[secret] = true;
+
+        search(&mut ecs);
+        assert!(ecs.fetch::<GameMap>().secret_doors[secret], "too far away to find it");
+
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<Position>().insert(player, Position { x: 5, y: 10 }).unwrap();
+        *ecs.write_resource::<Point>() = Point::new(5, 10);
+        for _ in 0..20 {
+            search(&mut ecs);
+        }
+        let map = ecs.fetch::<GameMap>();
+        assert_eq!(map.tiles[secret], TileType::Floor);
+        assert!(!map.blocked[secret] && !rltk::BaseMap::is_opaque(&*map, secret));
+        assert!(ecs.fetch::<GameLog>().entries.iter().any(|entry| entry.fragments[0].text == "You find a secret passage."));
+    }
+
+    #[test]
+    fn wanderers_stop_turning_up_once_the_street_is_crowded() {
+        let mut gs = State::new(fixture_world(), false);
+        {
+            let mut map = gs.ecs.fetch_mut::<GameMap>();
+            map.outdoors = true;
+            for x in 22..=30 {
+                let idx = map.xy_idx(x, 10);
+                map.tiles[idx] = TileType::Road;
+            }
+        }
+        gs.ecs.insert(GameClock { turn: 23 * clock::TURNS_PER_HOUR });
+
+        // the clock never moves here, so every call is on the hour
+        for _ in 0..20 {
+            gs.spawn_wanderer_on_the_hour();
+        }
+        assert!(spawner::street_is_full(&gs.ecs));
+        let crowd = gs.ecs.read_storage::<Wanderer>().join().count();
+        assert!(crowd < 20, "{} wanderers turned up", crowd);
+
+        let wanderer = (&gs.ecs.entities(), &gs.ecs.read_storage::<Wanderer>()).join().next().unwrap().0;
+        gs.ecs.delete_entity(wanderer).unwrap();
+        gs.spawn_wanderer_on_the_hour();
+        assert_eq!(gs.ecs.read_storage::<Wanderer>().join().count(), crowd, "one more once there's room");
+    }
 }
@@ -1,6 +1,6 @@
 use specs::prelude::*;
 use crate::game_map::GameMap;
-use crate::components::{Position, BlocksTile};
+use crate::components::{Position, BlocksTile, BlocksVisibility, Door, Locked};
 
 pub struct MapIndexingSystem;
 
@@ -8,10 +8,13 @@ impl<'a> System<'a> for MapIndexingSystem {
     type SystemData = ( WriteExpect<'a, GameMap>,
                         ReadStorage<'a, Position>,
                         ReadStorage<'a, BlocksTile>,
+                        ReadStorage<'a, BlocksVisibility>,
+                        ReadStorage<'a, Door>,
+                        ReadStorage<'a, Locked>,
                         Entities<'a> );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, positions, blockers, entities) = data;
+        let (mut map, positions, blockers, view_blockers, doors, locks, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
@@ -22,6 +25,12 @@ impl<'a> System<'a> for MapIndexingSystem {
             if let Some(_p) = blockers.get(entity) {
                 map.blocked[idx] = true;
             }
+            if view_blockers.contains(entity) {
+                map.view_blocked[idx] = true;
+            }
+            if doors.get(entity).is_some_and(|door| !door.open) && !locks.contains(entity) {
+                map.openable_doors[idx] = true;
+            }
 
             // push entity to the appropriate index. It's a copy type
             map.tile_content[idx].push(entity);
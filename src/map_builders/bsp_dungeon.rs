@@ -0,0 +1,224 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use super::MapBuilder;
+use super::common::{apply_room_to_map, doorways, fallback_room, stairs_position, snapshot};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::rect::Rect;
+use crate::spawner;
+
+
+/// Recursively splits the map into quarters and drops a room into the leaves,
+/// which gives densely packed rooms that are then joined up left-to-right.
+pub struct BspDungeonBuilder {
+    map: GameMap,
+    starting_position: Position,
+    rooms: Vec<Rect>,
+    rects: Vec<Rect>,
+    history: Vec<GameMap>
+}
+
+impl BspDungeonBuilder {
+    pub fn new(depth: i32) -> BspDungeonBuilder {
+        BspDungeonBuilder {
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            rooms: Vec::new(),
+            rects: Vec::new(),
+            history: Vec::new()
+        }
+    }
+
+    fn partition_rooms(&mut self, rng: &mut RandomNumberGenerator) {
+        const MAX_ATTEMPTS: i32 = 240;
+
+        self.rects.clear();
+        self.rects.push(Rect::new(2, 2, self.map.width - 5, self.map.height - 5));
+        let first_room = self.rects[0];
+        self.add_subrects(first_room);
+
+        for _ in 0..MAX_ATTEMPTS {
+            let rect = self.get_random_rect(rng);
+            let candidate = self.get_random_sub_rect(rect, rng);
+
+            if self.is_possible(candidate) {
+                apply_room_to_map(&mut self.map, &candidate);
+                self.rooms.push(candidate);
+                self.add_subrects(rect);
+                self.history.push(snapshot(&self.map));
+            }
+        }
+
+        if self.rooms.is_empty() {
+            let room = fallback_room(&mut self.map);
+            self.rooms.push(room);
+        }
+        self.rooms.sort_by_key(|room| room.x1);
+
+        for i in 1..self.rooms.len() {
+            let room = self.rooms[i - 1];
+            let next_room = self.rooms[i];
+            let start_x = room.x1 + rng.roll_dice(1, i32::abs(room.x1 - room.x2));
+            let start_y = room.y1 + rng.roll_dice(1, i32::abs(room.y1 - room.y2));
+            let end_x = next_room.x1 + rng.roll_dice(1, i32::abs(next_room.x1 - next_room.x2));
+            let end_y = next_room.y1 + rng.roll_dice(1, i32::abs(next_room.y1 - next_room.y2));
+            self.draw_corridor(start_x, start_y, end_x, end_y);
+            self.history.push(snapshot(&self.map));
+        }
+
+        if let Some((stairs_x, stairs_y)) = stairs_position(&self.rooms) {
+            let stairs_idx = self.map.xy_idx(stairs_x, stairs_y);
+            self.map.tiles[stairs_idx] = TileType::DownStairs;
+            self.history.push(snapshot(&self.map));
+        }
+
+        let (start_x, start_y) = self.rooms[0].center();
+        self.starting_position = Position { x: start_x, y: start_y };
+    }
+
+    fn add_subrects(&mut self, rect: Rect) {
+        let width = i32::abs(rect.x1 - rect.x2);
+        let height = i32::abs(rect.y1 - rect.y2);
+        let half_width = i32::max(width / 2, 1);
+        let half_height = i32::max(height / 2, 1);
+
+        self.rects.push(Rect::new(rect.x1, rect.y1, half_width, half_height));
+        self.rects.push(Rect::new(rect.x1, rect.y1 + half_height, half_width, half_height));
+        self.rects.push(Rect::new(rect.x1 + half_width, rect.y1, half_width, half_height));
+        self.rects.push(Rect::new(rect.x1 + half_width, rect.y1 + half_height, half_width, half_height));
+    }
+
+    fn get_random_rect(&self, rng: &mut RandomNumberGenerator) -> Rect {
+        if self.rects.len() == 1 {
+            return self.rects[0];
+        }
+        let idx = (rng.roll_dice(1, self.rects.len() as i32) - 1) as usize;
+        self.rects[idx]
+    }
+
+    fn get_random_sub_rect(&self, rect: Rect, rng: &mut RandomNumberGenerator) -> Rect {
+        let rect_width = i32::abs(rect.x1 - rect.x2);
+        let rect_height = i32::abs(rect.y1 - rect.y2);
+
+        let w = i32::max(3, rng.roll_dice(1, i32::min(rect_width, 10)) - 1) + 1;
+        let h = i32::max(3, rng.roll_dice(1, i32::min(rect_height, 10)) - 1) + 1;
+        let x = rect.x1 + rng.roll_dice(1, 6) - 1;
+        let y = rect.y1 + rng.roll_dice(1, 6) - 1;
+
+        Rect::new(x, y, w, h)
+    }
+
+    /// A room fits if it, plus a two tile margin, is still solid rock and inside the map.
+    fn is_possible(&self, rect: Rect) -> bool {
+        for y in rect.y1 - 2 ..= rect.y2 + 2 {
+            for x in rect.x1 - 2 ..= rect.x2 + 2 {
+                if x < 1 || x > self.map.width - 2 || y < 1 || y > self.map.height - 2 {
+                    return false;
+                }
+
+                let idx = self.map.xy_idx(x, y);
+                if self.map.tiles[idx] != TileType::Wall {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn draw_corridor(&mut self, x1: i32, y1: i32, x2: i32, y2: i32) {
+        let mut x = x1;
+        let mut y = y1;
+
+        while x != x2 || y != y2 {
+            if x < x2 {
+                x += 1;
+            } else if x > x2 {
+                x -= 1;
+            } else if y < y2 {
+                y += 1;
+            } else if y > y2 {
+                y -= 1;
+            }
+
+            let idx = self.map.xy_idx(x, y);
+            self.map.tiles[idx] = TileType::Floor;
+        }
+    }
+}
+
+impl MapBuilder for BspDungeonBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.partition_rooms(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        for room in self.rooms.iter().skip(1) {
+            spawner::spawn_room(ecs, room);
+        }
+        spawner::spawn_doors(ecs, &doorways(&self.map, &self.rooms));
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rltk::{DijkstraMap, RandomNumberGenerator};
+    use super::BspDungeonBuilder;
+    use crate::map_builders::MapBuilder;
+    use crate::game_map::{TileType, MAPCOUNT};
+
+    #[test]
+    fn every_floor_tile_is_reachable_from_the_starting_room() {
+        for seed in 0..25 {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let mut builder = BspDungeonBuilder::new(1);
+            builder.build_map(&mut rng);
+
+            let mut map = builder.get_map();
+            map.populate_blocked();
+            let start = builder.get_starting_position();
+            let start_idx = map.xy_idx(start.x, start.y);
+            assert!(map.tiles[start_idx] == TileType::Floor, "seed {} starts inside a wall", seed);
+
+            let distances = DijkstraMap::new(map.width, map.height, &[start_idx], &map, MAPCOUNT as f32);
+            for (idx, tile) in map.tiles.iter().enumerate() {
+                if *tile == TileType::Floor {
+                    assert!(
+                        distances.map[idx] < f32::MAX,
+                        "seed {}: floor at ({}, {}) is unreachable",
+                        seed, idx as i32 % map.width, idx as i32 / map.width
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn falls_back_to_a_single_room_when_none_fit() {
+        // with no solid rock left, there's nowhere a room is allowed to go
+        let mut builder = BspDungeonBuilder::new(1);
+        for tile in builder.map.tiles.iter_mut() {
+            *tile = TileType::Floor;
+        }
+        builder.build_map(&mut RandomNumberGenerator::seeded(1));
+
+        assert_eq!(builder.rooms.len(), 1);
+        let map = builder.get_map();
+        let start = builder.get_starting_position();
+        let stairs: Vec<usize> = (0..map.tiles.len()).filter(|idx| map.tiles[*idx] == TileType::DownStairs).collect();
+        assert_eq!(stairs.len(), 1);
+        assert!(stairs[0] != map.xy_idx(start.x, start.y), "the stairs are under the player");
+    }
+}
@@ -0,0 +1,197 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+use super::MapBuilder;
+use super::common::{remove_unreachable_areas_returning_most_distant, generate_voronoi_spawn_regions, snapshot};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::spawner;
+
+
+/// Abandoned silver mine: random noise smoothed out into caves, with anything
+/// that isn't connected to the entrance filled back in.
+pub struct CellularAutomataBuilder {
+    map: GameMap,
+    starting_position: Position,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
+    history: Vec<GameMap>
+}
+
+impl CellularAutomataBuilder {
+    pub fn new(depth: i32) -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            noise_areas: BTreeMap::new(),
+            history: Vec::new()
+        }
+    }
+
+    fn random_fill(&mut self, rng: &mut RandomNumberGenerator) {
+        for y in 1 .. self.map.height - 1 {
+            for x in 1 .. self.map.width - 1 {
+                let idx = self.map.xy_idx(x, y);
+                self.map.tiles[idx] = if rng.roll_dice(1, 100) > 55 {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
+            }
+        }
+    }
+
+    /// A tile becomes rock if it's crowded by rock, or sitting alone in open space.
+    fn smooth(&mut self) {
+        let mut new_tiles = self.map.tiles.clone();
+
+        for y in 1 .. self.map.height - 1 {
+            for x in 1 .. self.map.width - 1 {
+                let neighbors = (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+                    .filter(|&(dx, dy)| dx != 0 || dy != 0)
+                    .filter(|&(dx, dy)| self.map.tiles[self.map.xy_idx(x + dx, y + dy)] == TileType::Wall)
+                    .count();
+
+                let idx = self.map.xy_idx(x, y);
+                new_tiles[idx] = if neighbors > 4 || neighbors == 0 {
+                    TileType::Wall
+                } else {
+                    TileType::Floor
+                };
+            }
+        }
+
+        self.map.tiles = new_tiles;
+    }
+
+    fn dig_caves(&mut self, rng: &mut RandomNumberGenerator) {
+        const SMOOTHING_PASSES: i32 = 15;
+
+        self.random_fill(rng);
+        self.history.push(snapshot(&self.map));
+        for _ in 0..SMOOTHING_PASSES {
+            self.smooth();
+            self.history.push(snapshot(&self.map));
+        }
+
+        // start from the middle, walking left until we hit open ground
+        self.starting_position = Position { x: self.map.width / 2, y: self.map.height / 2 };
+        let mut start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        while self.map.tiles[start_idx] != TileType::Floor {
+            self.starting_position.x -= 1;
+            if self.starting_position.x < 1 {
+                self.starting_position.x = self.map.width - 2;
+                self.starting_position.y -= 1;
+            }
+            start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        }
+
+        let exit_idx = remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
+        self.map.tiles[exit_idx] = TileType::DownStairs;
+        self.history.push(snapshot(&self.map));
+
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.dig_caves(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        for area in self.noise_areas.values() {
+            if !area.contains(&start_idx) {
+                spawner::spawn_region(ecs, area);
+            }
+        }
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rltk::{DijkstraMap, RandomNumberGenerator};
+    use specs::prelude::*;
+    use super::CellularAutomataBuilder;
+    use crate::map_builders::MapBuilder;
+    use crate::components::{Name, Position};
+    use crate::game_map::{TileType, MAPCOUNT};
+    use crate::snapshot::{assert_snapshot, fixture_world, map_to_text};
+
+    fn build(seed: u64) -> CellularAutomataBuilder {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mut builder = CellularAutomataBuilder::new(3);
+        builder.build_map(&mut rng);
+        builder
+    }
+
+    #[test]
+    fn caves_match_snapshot() {
+        let builder = build(7);
+        assert_snapshot("cellular_automata_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn caves_are_connected_with_the_stairs_in_reach() {
+        for seed in 0..10 {
+            let builder = build(seed);
+            let mut map = builder.get_map();
+            let start = builder.get_starting_position();
+            let start_idx = map.xy_idx(start.x, start.y);
+            assert!(map.tiles[start_idx] == TileType::Floor, "seed {} starts inside rock", seed);
+
+            map.populate_blocked();
+            let distances = DijkstraMap::new(map.width, map.height, &[start_idx], &map, MAPCOUNT as f32);
+            assert!(
+                map.blocked.iter().zip(distances.map.iter()).all(|(blocked, distance)| *blocked || *distance < f32::MAX),
+                "seed {} left unreachable floor", seed
+            );
+
+            let stairs: Vec<usize> = (0..map.tiles.len()).filter(|idx| map.tiles[*idx] == TileType::DownStairs).collect();
+            assert_eq!(stairs.len(), 1, "seed {}", seed);
+            let stairs_distance = distances.map[stairs[0]];
+            assert!(stairs_distance < f32::MAX, "seed {} can't reach the stairs", seed);
+            assert!(
+                map.tiles.iter().zip(distances.map.iter()).all(|(tile, distance)| *tile != TileType::Floor || *distance <= stairs_distance),
+                "seed {} put the stairs short of the far end", seed
+            );
+
+            assert!(!builder.noise_areas.is_empty());
+            for area in builder.noise_areas.values() {
+                assert!(area.iter().all(|idx| map.tiles[*idx] == TileType::Floor), "seed {} spawns in rock", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn a_seeded_cave_spawns_the_same_things_every_time() {
+        let spawned = || {
+            let mut builder = build(7);
+            let mut ecs = fixture_world();
+            ecs.insert(builder.get_map());
+            builder.spawn_entities(&mut ecs);
+            let names = ecs.read_storage::<Name>();
+            let positions = ecs.read_storage::<Position>();
+            let found: Vec<(String, i32, i32)> = (&names, &positions).join()
+                .map(|(name, pos)| (name.name.clone(), pos.x, pos.y))
+                .collect();
+            found
+        };
+        let first = spawned();
+        assert!(first.len() > 3);
+        for _ in 0..5 {
+            assert_eq!(spawned(), first);
+        }
+    }
+}
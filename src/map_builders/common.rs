@@ -0,0 +1,184 @@
+use rltk::{
+    DijkstraMap, FastNoise, NoiseType, CellularDistanceFunction,
+    RandomNumberGenerator
+};
+use std::cmp::{min, max};
+use std::collections::BTreeMap;
+use crate::game_map::{GameMap, TileType, MAPCOUNT};
+use crate::rect::Rect;
+
+
+/// A copy of the map with everything revealed, so the visualizer can draw it.
+pub fn snapshot(map: &GameMap) -> GameMap {
+    let mut snapshot = map.clone();
+    for revealed in snapshot.revealed_tiles.iter_mut() {
+        *revealed = true;
+    }
+    snapshot
+}
+
+pub fn apply_room_to_map(map: &mut GameMap, room: &Rect) {
+    for y in room.y1 + 1 ..= room.y2 {
+        for x in room.x1 + 1 ..= room.x2 {
+            let index = map.xy_idx(x, y);
+            map.tiles[index] = TileType::Floor;
+        }
+    }
+}
+
+/// A room carved out of the middle of the map, for a room builder that couldn't fit
+/// any in.
+pub fn fallback_room(map: &mut GameMap) -> Rect {
+    let room = Rect::new(map.width / 2 - 6, map.height / 2 - 4, 12, 8);
+    apply_room_to_map(map, &room);
+    room
+}
+
+/// Where the way down goes: the middle of the last room, or its far corner when that's
+/// also the room the player starts in.
+pub fn stairs_position(rooms: &[Rect]) -> Option<(i32, i32)> {
+    match rooms {
+        [] => None,
+        [only] => Some((only.x2, only.y2)),
+        [.., last] => Some(last.center())
+    }
+}
+
+pub fn apply_horizontal_tunnel(map: &mut GameMap, x1: i32, x2: i32, y: i32) {
+    let minx = min(x1, x2);
+    let maxx = max(x1, x2);
+    apply_room_to_map(map, &Rect::new(minx, y, maxx - minx, 1));
+}
+
+pub fn apply_vertical_tunnel(map: &mut GameMap, y1: i32, y2: i32, x: i32) {
+    let miny = min(y1, y2);
+    let maxy = max(y1, y2);
+    apply_room_to_map(map, &Rect::new(x, miny, 1, maxy - miny));
+}
+
+/// Tiles where a corridor passes through a room's wall: floor on the room's edge
+/// with wall to either side, so a door hung there would fill the gap.
+pub fn doorways(map: &GameMap, rooms: &[Rect]) -> Vec<usize> {
+    let is_wall = |x: i32, y: i32| x < 0 || x >= map.width || y < 0 || y >= map.height
+        || map.tiles[map.xy_idx(x, y)] == TileType::Wall;
+    let inside_a_room = |x: i32, y: i32| rooms.iter().any(|room| x > room.x1 && x <= room.x2 && y > room.y1 && y <= room.y2);
+
+    let mut doorways = Vec::new();
+    for room in rooms.iter() {
+        let mut edge = Vec::new();
+        for x in room.x1 + 1 ..= room.x2 {
+            edge.push((x, room.y1));
+            edge.push((x, room.y2 + 1));
+        }
+        for y in room.y1 + 1 ..= room.y2 {
+            edge.push((room.x1, y));
+            edge.push((room.x2 + 1, y));
+        }
+        for (x, y) in edge {
+            if x < 0 || x >= map.width || y < 0 || y >= map.height || inside_a_room(x, y) {
+                continue;
+            }
+            let idx = map.xy_idx(x, y);
+            let between_walls = (is_wall(x - 1, y) && is_wall(x + 1, y)) || (is_wall(x, y - 1) && is_wall(x, y + 1));
+            if map.tiles[idx] == TileType::Floor && between_walls && !doorways.contains(&idx) {
+                doorways.push(idx);
+            }
+        }
+    }
+    doorways
+}
+
+/// Walls off everything that can't be walked to from `start_idx`, and returns the
+/// reachable tile that is furthest away - a good spot for the way down.
+pub fn remove_unreachable_areas_returning_most_distant(map: &mut GameMap, start_idx: usize) -> usize {
+    map.populate_blocked();
+    let distances = DijkstraMap::new(map.width, map.height, &[start_idx], map, MAPCOUNT as f32);
+
+    let mut exit_tile = (start_idx, 0.0f32);
+    for (idx, tile) in map.tiles.iter_mut().enumerate() {
+        if *tile == TileType::Floor {
+            let distance = distances.map[idx];
+            if distance == f32::MAX {
+                *tile = TileType::Wall;
+            } else if distance > exit_tile.1 {
+                exit_tile = (idx, distance);
+            }
+        }
+    }
+
+    map.populate_blocked();
+    exit_tile.0
+}
+
+/// Chops the walkable parts of the map into blobs using cellular noise, so that
+/// maps without rooms still have somewhere to hand to the spawner.
+/// Kept in key order, so a seeded level spawns the same things every time.
+pub fn generate_voronoi_spawn_regions(map: &GameMap, rng: &mut RandomNumberGenerator) -> BTreeMap<i32, Vec<usize>> {
+    let mut noise_areas: BTreeMap<i32, Vec<usize>> = BTreeMap::new();
+    let mut noise = FastNoise::seeded(rng.roll_dice(1, 65536) as u64);
+    noise.set_noise_type(NoiseType::Cellular);
+    noise.set_frequency(0.08);
+    noise.set_cellular_distance_function(CellularDistanceFunction::Manhattan);
+
+    for y in 1 .. map.height - 1 {
+        for x in 1 .. map.width - 1 {
+            let idx = map.xy_idx(x, y);
+            if map.tiles[idx] == TileType::Floor {
+                let cell_value = (noise.get_noise(x as f32, y as f32) * 10240.0) as i32;
+                noise_areas.entry(cell_value).or_default().push(idx);
+            }
+        }
+    }
+
+    noise_areas
+}
+
+/// True if every walkable tile on the map can be reached from `start_idx`.
+pub fn is_fully_connected(map: &mut GameMap, start_idx: usize) -> bool {
+    map.populate_blocked();
+    let distances = DijkstraMap::new(map.width, map.height, &[start_idx], map, MAPCOUNT as f32);
+
+    map.blocked.iter().zip(distances.map.iter())
+        .all(|(blocked, distance)| *blocked || *distance < f32::MAX)
+}
+
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Symmetry { None, Horizontal, Vertical, Both }
+
+/// Digs a brush-sized hole at x, y, mirrored across the middle of the map as asked.
+pub fn paint(map: &mut GameMap, mode: Symmetry, brush_size: i32, x: i32, y: i32) {
+    let center_x = map.width / 2;
+    let center_y = map.height / 2;
+    let mirror_x = center_x - (x - center_x);
+    let mirror_y = center_y - (y - center_y);
+
+    apply_paint(map, brush_size, x, y);
+    match mode {
+        Symmetry::None => {},
+        Symmetry::Horizontal => apply_paint(map, brush_size, mirror_x, y),
+        Symmetry::Vertical => apply_paint(map, brush_size, x, mirror_y),
+        Symmetry::Both => {
+            apply_paint(map, brush_size, mirror_x, y);
+            apply_paint(map, brush_size, x, mirror_y);
+            apply_paint(map, brush_size, mirror_x, mirror_y);
+        }
+    }
+}
+
+fn apply_paint(map: &mut GameMap, brush_size: i32, x: i32, y: i32) {
+    let half_brush = brush_size / 2;
+    for brush_y in y - half_brush .. y - half_brush + brush_size {
+        for brush_x in x - half_brush .. x - half_brush + brush_size {
+            if brush_x > 1 && brush_x < map.width - 1 && brush_y > 1 && brush_y < map.height - 1 {
+                let idx = map.xy_idx(brush_x, brush_y);
+                map.tiles[idx] = TileType::Floor;
+            }
+        }
+    }
+}
+
+pub fn floor_fraction(map: &GameMap) -> f32 {
+    let floor = map.tiles.iter().filter(|tile| **tile == TileType::Floor).count();
+    floor as f32 / map.tiles.len() as f32
+}
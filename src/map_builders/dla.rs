@@ -0,0 +1,249 @@
+use rltk::{Point, RandomNumberGenerator, LineAlg};
+use specs::prelude::*;
+use std::collections::BTreeMap;
+use super::MapBuilder;
+use super::common::{
+    remove_unreachable_areas_returning_most_distant, generate_voronoi_spawn_regions,
+    paint, floor_fraction, snapshot, Symmetry
+};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::spawner;
+
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DlaAlgorithm { WalkInwards, WalkOutwards, CentralAttractor }
+
+#[derive(Copy, Clone, Debug)]
+pub struct DlaSettings {
+    pub algorithm: DlaAlgorithm,
+    /// Growth stops once this fraction of the map is floor...
+    pub floor_percent: f32,
+    /// ...or once this many particles have stuck.
+    pub max_walkers: i32,
+    pub brush_size: i32,
+    pub symmetry: Symmetry
+}
+
+/// Diffusion-limited aggregation: particles wander until they bump into the
+/// dug-out area and stick to it, which grows branching, coral-like caverns.
+pub struct DlaBuilder {
+    map: GameMap,
+    starting_position: Position,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
+    settings: DlaSettings,
+    history: Vec<GameMap>
+}
+
+impl DlaBuilder {
+    pub fn new(depth: i32, settings: DlaSettings) -> DlaBuilder {
+        DlaBuilder {
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            noise_areas: BTreeMap::new(),
+            settings,
+            history: Vec::new()
+        }
+    }
+
+    pub fn walk_inwards(depth: i32) -> DlaBuilder {
+        DlaBuilder::new(depth, DlaSettings {
+            algorithm: DlaAlgorithm::WalkInwards,
+            floor_percent: 0.25,
+            max_walkers: 5000,
+            brush_size: 1,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn walk_outwards(depth: i32) -> DlaBuilder {
+        DlaBuilder::new(depth, DlaSettings {
+            algorithm: DlaAlgorithm::WalkOutwards,
+            floor_percent: 0.25,
+            max_walkers: 5000,
+            brush_size: 2,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn central_attractor(depth: i32) -> DlaBuilder {
+        DlaBuilder::new(depth, DlaSettings {
+            algorithm: DlaAlgorithm::CentralAttractor,
+            floor_percent: 0.25,
+            max_walkers: 5000,
+            brush_size: 2,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn insectoid(depth: i32) -> DlaBuilder {
+        DlaBuilder::new(depth, DlaSettings {
+            algorithm: DlaAlgorithm::CentralAttractor,
+            floor_percent: 0.25,
+            max_walkers: 5000,
+            brush_size: 2,
+            symmetry: Symmetry::Horizontal
+        })
+    }
+
+    fn aggregate(&mut self, rng: &mut RandomNumberGenerator) {
+        self.starting_position = Position { x: self.map.width / 2, y: self.map.height / 2 };
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+
+        // a small cross to give the first particles something to stick to
+        for (dx, dy) in [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            let idx = self.map.xy_idx(self.starting_position.x + dx, self.starting_position.y + dy);
+            self.map.tiles[idx] = TileType::Floor;
+        }
+
+        let mut walkers = 0;
+        while floor_fraction(&self.map) < self.settings.floor_percent && walkers < self.settings.max_walkers {
+            let (x, y) = match self.settings.algorithm {
+                DlaAlgorithm::WalkInwards => self.walk_inwards_from_random_point(rng),
+                DlaAlgorithm::WalkOutwards => self.walk_outwards_from_start(rng),
+                DlaAlgorithm::CentralAttractor => self.fall_towards_center(rng)
+            };
+            paint(&mut self.map, self.settings.symmetry, self.settings.brush_size, x, y);
+            walkers += 1;
+            if walkers % 10 == 0 {
+                self.history.push(snapshot(&self.map));
+            }
+        }
+
+        let exit_idx = remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
+        self.map.tiles[exit_idx] = TileType::DownStairs;
+        self.history.push(snapshot(&self.map));
+
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
+    }
+
+    fn random_point(&self, rng: &mut RandomNumberGenerator) -> (i32, i32) {
+        (rng.roll_dice(1, self.map.width - 3) + 1, rng.roll_dice(1, self.map.height - 3) + 1)
+    }
+
+    fn stumble(&self, rng: &mut RandomNumberGenerator, x: &mut i32, y: &mut i32) {
+        match rng.roll_dice(1, 4) {
+            1 => if *x > 2 { *x -= 1 },
+            2 => if *x < self.map.width - 2 { *x += 1 },
+            3 => if *y > 2 { *y -= 1 },
+            _ => if *y < self.map.height - 2 { *y += 1 }
+        }
+    }
+
+    /// Wanders in from somewhere random, sticking on the last rock tile before open ground.
+    fn walk_inwards_from_random_point(&self, rng: &mut RandomNumberGenerator) -> (i32, i32) {
+        let (mut x, mut y) = self.random_point(rng);
+        let (mut prev_x, mut prev_y) = (x, y);
+
+        while self.map.tiles[self.map.xy_idx(x, y)] == TileType::Wall {
+            prev_x = x;
+            prev_y = y;
+            self.stumble(rng, &mut x, &mut y);
+        }
+
+        (prev_x, prev_y)
+    }
+
+    /// Wanders out from the middle until it reaches rock, and digs there.
+    fn walk_outwards_from_start(&self, rng: &mut RandomNumberGenerator) -> (i32, i32) {
+        let (mut x, mut y) = (self.starting_position.x, self.starting_position.y);
+
+        while self.map.tiles[self.map.xy_idx(x, y)] == TileType::Floor {
+            self.stumble(rng, &mut x, &mut y);
+        }
+
+        (x, y)
+    }
+
+    /// Falls in a straight line towards the middle, sticking just before open ground.
+    fn fall_towards_center(&self, rng: &mut RandomNumberGenerator) -> (i32, i32) {
+        let (x, y) = self.random_point(rng);
+        let path = rltk::line2d(
+            LineAlg::Bresenham,
+            Point::new(x, y),
+            Point::new(self.starting_position.x, self.starting_position.y)
+        );
+
+        let mut previous = (x, y);
+        for step in path.iter() {
+            if self.map.tiles[self.map.xy_idx(step.x, step.y)] != TileType::Wall {
+                break;
+            }
+            previous = (step.x, step.y);
+        }
+
+        previous
+    }
+}
+
+impl MapBuilder for DlaBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.aggregate(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        for area in self.noise_areas.values() {
+            if !area.contains(&start_idx) {
+                spawner::spawn_region(ecs, area);
+            }
+        }
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rltk::RandomNumberGenerator;
+    use super::DlaBuilder;
+    use crate::map_builders::MapBuilder;
+    use crate::map_builders::common::is_fully_connected;
+    use crate::snapshot::{assert_snapshot, map_to_text};
+
+    fn build(mut builder: DlaBuilder, seed: u64) -> DlaBuilder {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        builder.build_map(&mut rng);
+        builder
+    }
+
+    #[test]
+    fn walk_inwards_matches_snapshot() {
+        let builder = build(DlaBuilder::walk_inwards(4), 7);
+        assert_snapshot("dla_walk_inwards_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn insectoid_matches_snapshot() {
+        let builder = build(DlaBuilder::insectoid(4), 7);
+        assert_snapshot("dla_insectoid_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn every_preset_is_connected() {
+        let presets: Vec<fn(i32) -> DlaBuilder> = vec![
+            DlaBuilder::walk_inwards,
+            DlaBuilder::walk_outwards,
+            DlaBuilder::central_attractor,
+            DlaBuilder::insectoid
+        ];
+
+        for (seed, preset) in presets.into_iter().enumerate() {
+            let builder = build(preset(4), seed as u64);
+            let mut map = builder.get_map();
+            let start = builder.get_starting_position();
+            let start_idx = map.xy_idx(start.x, start.y);
+            assert!(is_fully_connected(&mut map, start_idx), "preset {} left unreachable floor", seed);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+use super::MapBuilder;
+use super::common::{
+    remove_unreachable_areas_returning_most_distant, generate_voronoi_spawn_regions,
+    paint, floor_fraction, snapshot, Symmetry
+};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::spawner;
+
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DrunkSpawnMode { StartingPoint, Random }
+
+#[derive(Copy, Clone, Debug)]
+pub struct DrunkardSettings {
+    pub spawn_mode: DrunkSpawnMode,
+    /// How many steps each drunk takes before passing out.
+    pub lifetime: i32,
+    /// Digging stops once this fraction of the map is floor...
+    pub floor_percent: f32,
+    /// ...or once this many drunks have had a go.
+    pub max_walkers: i32,
+    pub brush_size: i32,
+    pub symmetry: Symmetry
+}
+
+/// Lets miners stumble about at random, digging wherever they step.
+pub struct DrunkardsWalkBuilder {
+    map: GameMap,
+    starting_position: Position,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
+    settings: DrunkardSettings,
+    history: Vec<GameMap>
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(depth: i32, settings: DrunkardSettings) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder {
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            noise_areas: BTreeMap::new(),
+            settings,
+            history: Vec::new()
+        }
+    }
+
+    pub fn open_area(depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(depth, DrunkardSettings {
+            spawn_mode: DrunkSpawnMode::StartingPoint,
+            lifetime: 400,
+            floor_percent: 0.5,
+            max_walkers: 1000,
+            brush_size: 1,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn open_halls(depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(depth, DrunkardSettings {
+            spawn_mode: DrunkSpawnMode::Random,
+            lifetime: 400,
+            floor_percent: 0.5,
+            max_walkers: 1000,
+            brush_size: 1,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn winding_passages(depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(depth, DrunkardSettings {
+            spawn_mode: DrunkSpawnMode::Random,
+            lifetime: 100,
+            floor_percent: 0.4,
+            max_walkers: 1000,
+            brush_size: 1,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn fat_passages(depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(depth, DrunkardSettings {
+            spawn_mode: DrunkSpawnMode::Random,
+            lifetime: 100,
+            floor_percent: 0.4,
+            max_walkers: 1000,
+            brush_size: 2,
+            symmetry: Symmetry::None
+        })
+    }
+
+    pub fn fearful_symmetry(depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(depth, DrunkardSettings {
+            spawn_mode: DrunkSpawnMode::Random,
+            lifetime: 100,
+            floor_percent: 0.4,
+            max_walkers: 1000,
+            brush_size: 1,
+            symmetry: Symmetry::Both
+        })
+    }
+
+    pub fn mirrored_drifts(depth: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder::new(depth, DrunkardSettings {
+            spawn_mode: DrunkSpawnMode::Random,
+            lifetime: 200,
+            floor_percent: 0.45,
+            max_walkers: 1000,
+            brush_size: 2,
+            symmetry: Symmetry::Vertical
+        })
+    }
+
+    fn stagger(&mut self, rng: &mut RandomNumberGenerator) {
+        self.starting_position = Position { x: self.map.width / 2, y: self.map.height / 2 };
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        self.map.tiles[start_idx] = TileType::Floor;
+
+        let mut walkers = 0;
+        while floor_fraction(&self.map) < self.settings.floor_percent && walkers < self.settings.max_walkers {
+            let (mut x, mut y) = if walkers == 0 || self.settings.spawn_mode == DrunkSpawnMode::StartingPoint {
+                (self.starting_position.x, self.starting_position.y)
+            } else {
+                (rng.roll_dice(1, self.map.width - 3) + 1, rng.roll_dice(1, self.map.height - 3) + 1)
+            };
+
+            for _ in 0..self.settings.lifetime {
+                paint(&mut self.map, self.settings.symmetry, self.settings.brush_size, x, y);
+                match rng.roll_dice(1, 4) {
+                    1 => if x > 2 { x -= 1 },
+                    2 => if x < self.map.width - 2 { x += 1 },
+                    3 => if y > 2 { y -= 1 },
+                    _ => if y < self.map.height - 2 { y += 1 }
+                }
+            }
+
+            walkers += 1;
+            self.history.push(snapshot(&self.map));
+        }
+
+        let exit_idx = remove_unreachable_areas_returning_most_distant(&mut self.map, start_idx);
+        self.map.tiles[exit_idx] = TileType::DownStairs;
+        self.history.push(snapshot(&self.map));
+
+        self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.stagger(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        for area in self.noise_areas.values() {
+            if !area.contains(&start_idx) {
+                spawner::spawn_region(ecs, area);
+            }
+        }
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rltk::RandomNumberGenerator;
+    use super::DrunkardsWalkBuilder;
+    use crate::map_builders::MapBuilder;
+    use crate::map_builders::common::is_fully_connected;
+    use crate::snapshot::{assert_snapshot, map_to_text};
+
+    fn build(mut builder: DrunkardsWalkBuilder, seed: u64) -> DrunkardsWalkBuilder {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        builder.build_map(&mut rng);
+        builder
+    }
+
+    #[test]
+    fn open_area_matches_snapshot() {
+        let builder = build(DrunkardsWalkBuilder::open_area(4), 7);
+        assert_snapshot("drunkard_open_area_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn fearful_symmetry_matches_snapshot() {
+        let builder = build(DrunkardsWalkBuilder::fearful_symmetry(4), 7);
+        assert_snapshot("drunkard_fearful_symmetry_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn every_preset_is_connected() {
+        let presets: Vec<fn(i32) -> DrunkardsWalkBuilder> = vec![
+            DrunkardsWalkBuilder::open_area,
+            DrunkardsWalkBuilder::open_halls,
+            DrunkardsWalkBuilder::winding_passages,
+            DrunkardsWalkBuilder::fat_passages,
+            DrunkardsWalkBuilder::fearful_symmetry,
+            DrunkardsWalkBuilder::mirrored_drifts
+        ];
+
+        for (seed, preset) in presets.into_iter().enumerate() {
+            let builder = build(preset(4), seed as u64);
+            let mut map = builder.get_map();
+            let start = builder.get_starting_position();
+            let start_idx = map.xy_idx(start.x, start.y);
+            assert!(is_fully_connected(&mut map, start_idx), "preset {} left unreachable floor", seed);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use crate::components::Position;
+use crate::game_map::GameMap;
+
+mod common;
+mod simple_map;
+mod bsp_dungeon;
+mod cellular_automata;
+mod town;
+mod prefabs;
+mod prefab_builder;
+mod waveform_collapse;
+mod drunkard;
+mod dla;
+mod secret_doors;
+
+use simple_map::SimpleMapBuilder;
+use bsp_dungeon::BspDungeonBuilder;
+use cellular_automata::CellularAutomataBuilder;
+use town::TownBuilder;
+use prefab_builder::PrefabBuilder;
+use waveform_collapse::WaveformCollapseBuilder;
+use prefabs::CELLAR_SAMPLE;
+use drunkard::DrunkardsWalkBuilder;
+use dla::DlaBuilder;
+use secret_doors::SecretDoorBuilder;
+pub use town::{TownBuilding, BuildingKind};
+
+
+pub trait MapBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator);
+    fn spawn_entities(&mut self, ecs: &mut World);
+    fn get_map(&self) -> GameMap;
+    fn get_starting_position(&self) -> Position;
+    /// Snapshots of the map as it was being built, for the generation visualizer.
+    fn get_snapshot_history(&self) -> Vec<GameMap>;
+}
+
+
+/// The ghost town sits on the surface, at the top of the mine.
+const TOWN_DEPTH: i32 = 1;
+/// Depth at which the cellars give way to the old silver mines.
+pub const MINE_DEPTH: i32 = 4;
+
+
+/// What a level in the cellars might be built with. Each entry is as likely as any other.
+const CELLAR_BUILDERS: &[&str] = &[
+    "bsp", "wfc-bsp", "wfc-cellar", "winding-passages", "fat-passages", "fearful-symmetry", "simple"
+];
+/// And down in the mines, where the caves come up twice as often.
+const MINE_BUILDERS: &[&str] = &[
+    "wfc-caves", "open-area", "open-halls", "walk-inwards", "walk-outwards",
+    "central-attractor", "insectoid", "mirrored-drifts", "caves", "caves"
+];
+
+
+/// The map generator called `name`, as rolled for by `random_builder` or picked on the
+/// command line with `--builder=<name>`. Everything below the town gets vaults and
+/// secret passages added.
+pub fn named_builder(name: &str, depth: i32) -> Option<Box<dyn MapBuilder>> {
+    let builder: Box<dyn MapBuilder> = match name {
+        "town" => return Some(Box::new(TownBuilder::new(depth))),
+        "simple" => Box::new(SimpleMapBuilder::new(depth)),
+        "bsp" => Box::new(BspDungeonBuilder::new(depth)),
+        "caves" => Box::new(CellularAutomataBuilder::new(depth)),
+        "wfc-bsp" => Box::new(WaveformCollapseBuilder::derived_map(depth, Box::new(BspDungeonBuilder::new(depth)))),
+        "wfc-caves" => Box::new(WaveformCollapseBuilder::derived_map(depth, Box::new(CellularAutomataBuilder::new(depth)))),
+        "wfc-cellar" => Box::new(WaveformCollapseBuilder::from_prefab(depth, CELLAR_SAMPLE.load(), Box::new(SimpleMapBuilder::new(depth)))),
+        "open-area" => Box::new(DrunkardsWalkBuilder::open_area(depth)),
+        "open-halls" => Box::new(DrunkardsWalkBuilder::open_halls(depth)),
+        "winding-passages" => Box::new(DrunkardsWalkBuilder::winding_passages(depth)),
+        "fat-passages" => Box::new(DrunkardsWalkBuilder::fat_passages(depth)),
+        "fearful-symmetry" => Box::new(DrunkardsWalkBuilder::fearful_symmetry(depth)),
+        "mirrored-drifts" => Box::new(DrunkardsWalkBuilder::mirrored_drifts(depth)),
+        "walk-inwards" => Box::new(DlaBuilder::walk_inwards(depth)),
+        "walk-outwards" => Box::new(DlaBuilder::walk_outwards(depth)),
+        "central-attractor" => Box::new(DlaBuilder::central_attractor(depth)),
+        "insectoid" => Box::new(DlaBuilder::insectoid(depth)),
+        _ => return None
+    };
+    Some(Box::new(SecretDoorBuilder::new(Box::new(PrefabBuilder::vaults(builder)))))
+}
+
+/// Picks the map generator used for a given dungeon level: always the town on the
+/// surface, otherwise a roll on the table for that depth.
+pub fn random_builder(depth: i32, rng: &mut RandomNumberGenerator) -> Box<dyn MapBuilder> {
+    let name = if depth == TOWN_DEPTH {
+        "town"
+    } else {
+        let table = if depth >= MINE_DEPTH { MINE_BUILDERS } else { CELLAR_BUILDERS };
+        table[(rng.roll_dice(1, table.len() as i32) - 1) as usize]
+    };
+    named_builder(name, depth).expect("Every builder in the tables has a name")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_history_ends_with_the_finished_map() {
+        for seed in 0..20 {
+            for depth in 1..=5 {
+                let mut rng = RandomNumberGenerator::seeded(seed);
+                let mut builder = random_builder(depth, &mut rng);
+                builder.build_map(&mut rng);
+
+                let history = builder.get_snapshot_history();
+                let last = history.last().expect("builder recorded no snapshots");
+                assert!(last.tiles == builder.get_map().tiles, "seed {} depth {}", seed, depth);
+                assert!(last.revealed_tiles.iter().all(|revealed| *revealed));
+            }
+        }
+    }
+
+    #[test]
+    fn every_builder_in_the_tables_can_be_asked_for_by_name() {
+        for name in CELLAR_BUILDERS.iter().chain(MINE_BUILDERS.iter()).chain(["town"].iter()) {
+            assert!(named_builder(name, 2).is_some(), "{}", name);
+        }
+        assert!(named_builder("maze", 2).is_none());
+    }
+}
@@ -0,0 +1,166 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use super::MapBuilder;
+use super::common::{is_fully_connected, snapshot};
+use super::prefabs::{Prefab, VAULTS, glyph_to_tile, glyph_to_spawn};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::rect::Rect;
+use crate::spawner;
+
+
+/// Runs another builder, then stamps one of the hand-drawn vaults somewhere
+/// it fits without cutting off any part of the level.
+pub struct PrefabBuilder {
+    previous: Box<dyn MapBuilder>,
+    map: GameMap,
+    starting_position: Position,
+    stamped: Vec<Rect>,
+    spawns: Vec<(usize, &'static str)>,
+    history: Vec<GameMap>
+}
+
+impl PrefabBuilder {
+    pub fn vaults(previous: Box<dyn MapBuilder>) -> PrefabBuilder {
+        PrefabBuilder {
+            previous,
+            map: GameMap::new(0),
+            starting_position: Position { x: 0, y: 0 },
+            stamped: Vec::new(),
+            spawns: Vec::new(),
+            history: Vec::new()
+        }
+    }
+
+    fn place_vault(&mut self, rng: &mut RandomNumberGenerator) {
+        const PLACEMENT_ATTEMPTS: i32 = 30;
+
+        let depth = self.map.depth;
+        let candidates: Vec<_> = VAULTS.iter()
+            .filter(|vault| depth >= vault.min_depth && depth <= vault.max_depth)
+            .collect();
+        let vault = match rng.random_slice_entry(&candidates) {
+            None => return,
+            Some(vault) => vault
+        };
+
+        let mut prefab = vault.load();
+        for _ in 0..rng.roll_dice(1, 4) - 1 {
+            prefab = prefab.rotated();
+        }
+        if rng.roll_dice(1, 2) == 1 {
+            prefab = prefab.mirrored();
+        }
+
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let x = rng.roll_dice(1, self.map.width - prefab.width - 2);
+            let y = rng.roll_dice(1, self.map.height - prefab.height - 2);
+
+            if let Some(stamped) = self.try_stamp(&prefab, x, y, start_idx) {
+                self.map = stamped;
+                self.history.push(snapshot(&self.map));
+                self.stamped.push(Rect::new(x - 1, y - 1, prefab.width, prefab.height));
+                for py in 0..prefab.height {
+                    for px in 0..prefab.width {
+                        if let Some(name) = glyph_to_spawn(prefab.get(px, py)) {
+                            self.spawns.push((self.map.xy_idx(x + px, y + py), name));
+                        }
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    /// Stamps onto a copy of the map, keeping it only if the stairs and the
+    /// start are untouched and everything is still reachable afterwards.
+    fn try_stamp(&self, prefab: &Prefab, x: i32, y: i32, start_idx: usize) -> Option<GameMap> {
+        let mut map = self.map.clone();
+
+        for py in 0..prefab.height {
+            for px in 0..prefab.width {
+                if let Some(tile) = glyph_to_tile(prefab.get(px, py)) {
+                    let idx = map.xy_idx(x + px, y + py);
+                    if idx == start_idx || map.tiles[idx] == TileType::DownStairs {
+                        return None;
+                    }
+                    map.tiles[idx] = tile;
+                }
+            }
+        }
+
+        if is_fully_connected(&mut map, start_idx) {
+            Some(map)
+        } else {
+            None
+        }
+    }
+}
+
+impl MapBuilder for PrefabBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.previous.build_map(rng);
+        self.map = self.previous.get_map();
+        self.starting_position = self.previous.get_starting_position();
+        self.history = self.previous.get_snapshot_history();
+        self.place_vault(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        self.previous.spawn_entities(ecs);
+        for region in self.stamped.iter() {
+            spawner::clear_region(ecs, region);
+        }
+        for (idx, name) in self.spawns.iter() {
+            spawner::spawn_entity(ecs, *idx as i32 % self.map.width, *idx as i32 / self.map.width, name);
+        }
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::simple_map::SimpleMapBuilder;
+
+    /// An open room from (1, 1) to (20, 9), starting at its west end.
+    fn builder_over_a_room() -> (PrefabBuilder, usize) {
+        let mut builder = PrefabBuilder::vaults(Box::new(SimpleMapBuilder::new(2)));
+        for y in 1..=9 {
+            for x in 1..=20 {
+                let idx = builder.map.xy_idx(x, y);
+                builder.map.tiles[idx] = TileType::Floor;
+            }
+        }
+        let start_idx = builder.map.xy_idx(2, 5);
+        (builder, start_idx)
+    }
+
+    #[test]
+    fn stamps_that_would_cut_off_part_of_the_level_are_refused() {
+        let (builder, start_idx) = builder_over_a_room();
+
+        let pillar = Prefab::from_rows(&["#"]);
+        assert!(builder.try_stamp(&pillar, 10, 5, start_idx).is_some());
+        assert!(builder.try_stamp(&pillar, 2, 5, start_idx).is_none(), "not on top of the player");
+
+        let partition = Prefab::from_rows(&["#"; 9]);
+        assert!(builder.try_stamp(&partition, 10, 1, start_idx).is_none(), "the east half would be cut off");
+
+        let sealed_room = Prefab::from_rows(&["###", "#.#", "###"]);
+        assert!(builder.try_stamp(&sealed_room, 15, 4, start_idx).is_none());
+        let open_room = Prefab::from_rows(&["###", "#..", "###"]);
+        assert!(builder.try_stamp(&open_room, 15, 4, start_idx).is_some());
+    }
+}
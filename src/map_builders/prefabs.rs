@@ -0,0 +1,195 @@
+use rltk::rex::XpFile;
+use crate::game_map::TileType;
+
+
+/// A hand-drawn chunk of map, as glyphs. Blank cells are transparent and
+/// leave whatever was generated underneath alone.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Prefab {
+    pub width: i32,
+    pub height: i32,
+    cells: Vec<char>
+}
+
+pub struct PrefabDefinition {
+    pub name: &'static str,
+    xp: &'static [u8],
+    pub min_depth: i32,
+    pub max_depth: i32
+}
+
+pub const VAULTS: &[PrefabDefinition] = &[
+    PrefabDefinition {
+        name: "Treasure Vault",
+        xp: include_bytes!("../../resources/prefabs/treasure_vault.xp"),
+        min_depth: 2,
+        max_depth: 100
+    },
+    PrefabDefinition {
+        name: "Hideout Saloon",
+        xp: include_bytes!("../../resources/prefabs/hideout_saloon.xp"),
+        min_depth: 2,
+        max_depth: 5
+    },
+    PrefabDefinition {
+        name: "Warlord's Lair",
+        xp: include_bytes!("../../resources/prefabs/warlord_lair.xp"),
+        min_depth: 4,
+        max_depth: 100
+    }
+];
+
+/// A sample of winding cellar passages for the wave function collapse builder to learn from.
+pub const CELLAR_SAMPLE: PrefabDefinition = PrefabDefinition {
+    name: "Cellar Sample",
+    xp: include_bytes!("../../resources/prefabs/cellar_sample.xp"),
+    min_depth: 2,
+    max_depth: 3
+};
+
+
+impl PrefabDefinition {
+    pub fn load(&self) -> Prefab {
+        let xp = XpFile::read(&mut &self.xp[..])
+            .unwrap_or_else(|_| panic!("Unable to read prefab {}", self.name));
+        Prefab::from_xp(&xp)
+    }
+}
+
+impl Prefab {
+    /// Reads the glyphs of every layer, with higher layers drawn over lower ones.
+    pub fn from_xp(xp: &XpFile) -> Prefab {
+        let width = xp.layers[0].width;
+        let height = xp.layers[0].height;
+        let mut cells = vec![' '; width * height];
+
+        for layer in xp.layers.iter() {
+            for y in 0..height {
+                for x in 0..width {
+                    if let Some(cell) = layer.get(x, y) {
+                        if cell.ch != 0 && cell.ch != 32 {
+                            cells[y * width + x] = rltk::to_char(cell.ch as u8);
+                        }
+                    }
+                }
+            }
+        }
+
+        Prefab { width: width as i32, height: height as i32, cells }
+    }
+
+    /// A prefab written out as rows of glyphs, for tests.
+    #[cfg(test)]
+    pub fn from_rows(rows: &[&str]) -> Prefab {
+        let cells: Vec<char> = rows.iter().flat_map(|row| row.chars()).collect();
+        Prefab { width: cells.len() as i32 / rows.len() as i32, height: rows.len() as i32, cells }
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> char {
+        self.cells[(y * self.width + x) as usize]
+    }
+
+    /// Turns the prefab a quarter turn clockwise.
+    pub fn rotated(&self) -> Prefab {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..self.width {
+            for x in 0..self.height {
+                cells.push(self.get(y, self.height - 1 - x));
+            }
+        }
+        Prefab { width: self.height, height: self.width, cells }
+    }
+
+    /// Flips the prefab left to right.
+    pub fn mirrored(&self) -> Prefab {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                cells.push(self.get(self.width - 1 - x, y));
+            }
+        }
+        Prefab { width: self.width, height: self.height, cells }
+    }
+}
+
+
+/// The terrain under a prefab glyph, or `None` if the cell is transparent.
+pub fn glyph_to_tile(glyph: char) -> Option<TileType> {
+    match glyph {
+        ' ' => None,
+        '#' => Some(TileType::Wall),
+        '_' => Some(TileType::WoodFloor),
+        '>' => Some(TileType::DownStairs),
+        _ => Some(TileType::Floor)
+    }
+}
+
+/// The spawner name for an entity drawn into a prefab, if there is one.
+pub fn glyph_to_spawn(glyph: char) -> Option<&'static str> {
+    match glyph {
+        'g' => Some("Goblin"),
+        'o' => Some("Orc"),
+        'O' => Some("Orc Warlord"),
+        '!' => Some("Health Potion"),
+        'b' => Some("Bartender"),
+        '═' => Some("Bar Counter"),
+        'π' => Some("Card Table"),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_prefab() -> Vec<&'static PrefabDefinition> {
+        VAULTS.iter().chain(std::iter::once(&CELLAR_SAMPLE)).collect()
+    }
+
+    #[test]
+    fn every_embedded_prefab_loads_with_glyphs_we_know() {
+        let terrain = ['#', '_', '>', '.'];
+        for definition in every_prefab() {
+            let prefab = definition.load();
+            assert!(prefab.width > 0 && prefab.height > 0, "{} is empty", definition.name);
+            assert!(prefab.cells.contains(&'#'), "{} has no walls", definition.name);
+            for glyph in prefab.cells.iter().filter(|glyph| **glyph != ' ') {
+                assert!(
+                    terrain.contains(glyph) || glyph_to_spawn(*glyph).is_some(),
+                    "{} has a '{}' nothing knows how to build", definition.name, glyph
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn four_turns_or_two_flips_get_back_where_we_started() {
+        for definition in every_prefab() {
+            let prefab = definition.load();
+            let turned = prefab.rotated();
+            assert_eq!((turned.width, turned.height), (prefab.height, prefab.width));
+            assert_eq!(turned.rotated().rotated().rotated(), prefab, "{}", definition.name);
+            assert_eq!(prefab.mirrored().mirrored(), prefab, "{}", definition.name);
+        }
+
+        let prefab = Prefab::from_rows(&["ab", "cd", "ef"]);
+        assert_eq!(prefab.rotated(), Prefab::from_rows(&["eca", "fdb"]));
+        assert_eq!(prefab.mirrored(), Prefab::from_rows(&["ba", "dc", "fe"]));
+    }
+
+    #[test]
+    fn glyphs_map_to_terrain_and_spawns() {
+        assert_eq!(glyph_to_tile(' '), None);
+        assert_eq!(glyph_to_tile('#'), Some(TileType::Wall));
+        assert_eq!(glyph_to_tile('_'), Some(TileType::WoodFloor));
+        assert_eq!(glyph_to_tile('>'), Some(TileType::DownStairs));
+        assert_eq!(glyph_to_tile('.'), Some(TileType::Floor));
+        assert_eq!(glyph_to_tile('O'), Some(TileType::Floor), "whatever spawns stands on floor");
+
+        assert_eq!(glyph_to_spawn('O'), Some("Orc Warlord"));
+        assert_eq!(glyph_to_spawn('b'), Some("Bartender"));
+        assert_eq!(glyph_to_spawn('π'), Some("Card Table"));
+        assert_eq!(glyph_to_spawn('#'), None);
+        assert_eq!(glyph_to_spawn('.'), None);
+    }
+}
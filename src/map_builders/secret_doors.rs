@@ -0,0 +1,129 @@
+use rltk::{RandomNumberGenerator, DijkstraMap};
+use specs::prelude::*;
+use super::MapBuilder;
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType, MAPCOUNT};
+
+
+/// Runs another builder, then hides a few passages in walls that are only one tile
+/// thick. They look like any other wall until the player finds them, and always
+/// save a long walk around, so the level never depends on them.
+pub struct SecretDoorBuilder {
+    previous: Box<dyn MapBuilder>,
+    map: GameMap
+}
+
+impl SecretDoorBuilder {
+    pub fn new(previous: Box<dyn MapBuilder>) -> SecretDoorBuilder {
+        SecretDoorBuilder { previous, map: GameMap::new(0) }
+    }
+
+    /// Walls with open ground on two opposite sides and wall on the other two.
+    fn candidates(map: &GameMap) -> Vec<usize> {
+        let open = |x: i32, y: i32| map.tiles[map.xy_idx(x, y)] != TileType::Wall;
+        let mut candidates = Vec::new();
+        for y in 1 .. map.height - 1 {
+            for x in 1 .. map.width - 1 {
+                if open(x, y) {
+                    continue;
+                }
+                let across = open(x - 1, y) && open(x + 1, y) && !open(x, y - 1) && !open(x, y + 1);
+                let down = open(x, y - 1) && open(x, y + 1) && !open(x - 1, y) && !open(x + 1, y);
+                if across || down {
+                    candidates.push(map.xy_idx(x, y));
+                }
+            }
+        }
+        candidates
+    }
+
+    /// How far it is from one side of the wall to the other without going through it,
+    /// or `None` if there's no way round at all.
+    fn detour(map: &GameMap, idx: usize) -> Option<f32> {
+        let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+        let (from, to) = if map.tiles[map.xy_idx(x - 1, y)] != TileType::Wall {
+            (map.xy_idx(x - 1, y), map.xy_idx(x + 1, y))
+        } else {
+            (map.xy_idx(x, y - 1), map.xy_idx(x, y + 1))
+        };
+        let distance = DijkstraMap::new(map.width, map.height, &[from], map, MAPCOUNT as f32).map[to];
+        if distance == f32::MAX { None } else { Some(distance) }
+    }
+
+    fn hide_passages(&mut self, rng: &mut RandomNumberGenerator) {
+        const MIN_DETOUR: f32 = 12.0;
+        const ATTEMPTS: i32 = 20;
+
+        let mut map = self.map.clone();
+        map.populate_blocked();
+        let mut candidates = SecretDoorBuilder::candidates(&map);
+        let mut wanted = rng.roll_dice(1, 3) - 1;
+        for _ in 0..ATTEMPTS {
+            if wanted == 0 || candidates.is_empty() {
+                break;
+            }
+            let idx = candidates.remove(rng.random_slice_index(&candidates).unwrap());
+            if SecretDoorBuilder::detour(&map, idx).is_some_and(|steps| steps >= MIN_DETOUR) {
+                self.map.secret_doors[idx] = true;
+                wanted -= 1;
+            }
+        }
+    }
+}
+
+impl MapBuilder for SecretDoorBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.previous.build_map(rng);
+        self.map = self.previous.get_map();
+        self.hide_passages(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        self.previous.spawn_entities(ecs);
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.previous.get_starting_position()
+    }
+
+    // secret passages look like walls, so there's nothing new to show
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.previous.get_snapshot_history()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::random_builder;
+
+    #[test]
+    fn secret_passages_are_thin_walls_that_save_a_long_walk() {
+        let mut found = 0;
+        for seed in 0..20 {
+            let mut rng = RandomNumberGenerator::seeded(seed);
+            let mut builder = random_builder(2, &mut rng);
+            builder.build_map(&mut rng);
+            let mut map = builder.get_map();
+            map.populate_blocked();
+
+            let secrets: Vec<usize> = (0..map.tiles.len()).filter(|idx| map.secret_doors[*idx]).collect();
+            for idx in secrets.iter() {
+                found += 1;
+                assert_eq!(map.tiles[*idx], TileType::Wall);
+                assert!(SecretDoorBuilder::candidates(&map).contains(idx));
+                assert!(SecretDoorBuilder::detour(&map, *idx).unwrap() >= 12.0, "seed {}", seed);
+            }
+            for idx in secrets {
+                map.reveal_secret_door(idx);
+                assert_eq!(map.tiles[idx], TileType::Floor);
+                assert!(!map.blocked[idx] && !map.secret_doors[idx]);
+            }
+        }
+        assert!(found > 0, "no level had a secret passage");
+    }
+}
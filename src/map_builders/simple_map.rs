@@ -0,0 +1,100 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use super::MapBuilder;
+use super::common::{apply_room_to_map, apply_horizontal_tunnel, apply_vertical_tunnel, doorways, fallback_room, stairs_position, snapshot};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::rect::Rect;
+use crate::spawner;
+
+
+pub struct SimpleMapBuilder {
+    map: GameMap,
+    starting_position: Position,
+    rooms: Vec<Rect>,
+    history: Vec<GameMap>
+}
+
+impl SimpleMapBuilder {
+    pub fn new(depth: i32) -> SimpleMapBuilder {
+        SimpleMapBuilder {
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            rooms: Vec::new(),
+            history: Vec::new()
+        }
+    }
+
+    fn rooms_and_corridors(&mut self, rng: &mut RandomNumberGenerator) {
+        const MAX_ROOMS: i32 = 30;
+        const MIN_SIZE: i32 = 6;
+        const MAX_SIZE: i32 = 10;
+
+        'outer: for _ in 0..MAX_ROOMS {
+            let w = rng.range(MIN_SIZE, MAX_SIZE);
+            let h = rng.range(MIN_SIZE, MAX_SIZE);
+            let x = rng.roll_dice(1, self.map.width - w - 1) - 1;
+            let y = rng.roll_dice(1, self.map.height - h - 1) - 1;
+            let new_room = Rect::new(x, y, w, h);
+
+            for room in self.rooms.iter() {
+                if new_room.intersect(room) {
+                    continue 'outer;
+                }
+            }
+
+            apply_room_to_map(&mut self.map, &new_room);
+            if let Some(prev_room) = self.rooms.last() {
+                let (new_x, new_y) = new_room.center();
+                let (prev_x, prev_y) = prev_room.center();
+                if rng.roll_dice(1, 2) == 1 {
+                    apply_horizontal_tunnel(&mut self.map, prev_x, new_x, prev_y);
+                    apply_vertical_tunnel(&mut self.map, prev_y, new_y, new_x);
+                } else {
+                    apply_vertical_tunnel(&mut self.map, prev_y, new_y, prev_x);
+                    apply_horizontal_tunnel(&mut self.map, prev_x, new_x, new_y);
+                }
+            }
+
+            self.rooms.push(new_room);
+            self.history.push(snapshot(&self.map));
+        }
+
+        if self.rooms.is_empty() {
+            let room = fallback_room(&mut self.map);
+            self.rooms.push(room);
+        }
+        if let Some((stairs_x, stairs_y)) = stairs_position(&self.rooms) {
+            let stairs_idx = self.map.xy_idx(stairs_x, stairs_y);
+            self.map.tiles[stairs_idx] = TileType::DownStairs;
+            self.history.push(snapshot(&self.map));
+        }
+
+        let (start_x, start_y) = self.rooms[0].center();
+        self.starting_position = Position { x: start_x, y: start_y };
+    }
+}
+
+impl MapBuilder for SimpleMapBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.rooms_and_corridors(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        for room in self.rooms.iter().skip(1) {
+            spawner::spawn_room(ecs, room);
+        }
+        spawner::spawn_doors(ecs, &doorways(&self.map, &self.rooms));
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
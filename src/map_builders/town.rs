@@ -0,0 +1,254 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use super::MapBuilder;
+use super::common::snapshot;
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::rect::Rect;
+use crate::spawner;
+
+/// People (or worse) out on the street when the player rides in.
+const WANDERERS: i32 = 3;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum BuildingKind {
+    Saloon,
+    SheriffsOffice,
+    GeneralStore,
+    Bank,
+    Jail,
+    AbandonedHouse
+}
+
+/// A building on main street. `rect` is the interior floor (in the same
+/// `x1 + 1 ..= x2` convention as rooms), and `door` is the doorway's map index.
+#[derive(Clone)]
+pub struct TownBuilding {
+    pub kind: BuildingKind,
+    pub rect: Rect,
+    pub door: usize
+}
+
+/// The ghost town on the surface: one dusty main street running east to the
+/// mine, lined with buildings on both sides and open desert all around.
+pub struct TownBuilder {
+    map: GameMap,
+    starting_position: Position,
+    buildings: Vec<TownBuilding>,
+    hitching_posts: Vec<usize>,
+    history: Vec<GameMap>
+}
+
+impl TownBuilder {
+    pub fn new(depth: i32) -> TownBuilder {
+        TownBuilder {
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            buildings: Vec::new(),
+            hitching_posts: Vec::new(),
+            history: Vec::new()
+        }
+    }
+
+    fn lay_out_town(&mut self, rng: &mut RandomNumberGenerator) {
+        let street_y = self.map.height / 2;
+
+        self.fill_desert();
+        for y in street_y - 1 ..= street_y + 1 {
+            for x in 2 .. self.map.width - 2 {
+                let idx = self.map.xy_idx(x, y);
+                self.map.tiles[idx] = TileType::Road;
+            }
+        }
+        self.history.push(snapshot(&self.map));
+
+        // the north side faces the street with its bottom wall, the south side with its top
+        let mut lots = self.plot_lots(rng, street_y - 3, true);
+        lots.append(&mut self.plot_lots(rng, street_y + 3, false));
+
+        let mut kinds = vec![
+            BuildingKind::Saloon,
+            BuildingKind::SheriffsOffice,
+            BuildingKind::GeneralStore,
+            BuildingKind::Bank,
+            BuildingKind::Jail
+        ];
+        while kinds.len() < lots.len() {
+            kinds.push(BuildingKind::AbandonedHouse);
+        }
+        kinds.truncate(lots.len());
+
+        for (walls, faces_south) in lots {
+            let kind_idx = rng.random_slice_index(&kinds).unwrap();
+            let kind = kinds.remove(kind_idx);
+            self.build(kind, walls, faces_south, street_y);
+            self.history.push(snapshot(&self.map));
+        }
+
+        self.starting_position = Position { x: 3, y: street_y };
+        let mine_entrance = self.map.xy_idx(self.map.width - 3, street_y);
+        self.map.tiles[mine_entrance] = TileType::DownStairs;
+        self.history.push(snapshot(&self.map));
+    }
+
+    fn fill_desert(&mut self) {
+        for y in 0 .. self.map.height {
+            for x in 0 .. self.map.width {
+                let idx = self.map.xy_idx(x, y);
+                let edge = x == 0 || y == 0 || x == self.map.width - 1 || y == self.map.height - 1;
+                self.map.tiles[idx] = if edge { TileType::Wall } else { TileType::Sand };
+            }
+        }
+    }
+
+    /// Splits one side of the street into lots, returning the outer walls of each building.
+    fn plot_lots(&self, rng: &mut RandomNumberGenerator, street_edge: i32, north: bool) -> Vec<(Rect, bool)> {
+        let mut lots = Vec::new();
+        let mut x = 5 + rng.roll_dice(1, 3);
+
+        loop {
+            let w = rng.range(8, 13);
+            let h = rng.range(6, 9);
+            if x + w > self.map.width - 6 {
+                break;
+            }
+
+            let y = if north { street_edge - h } else { street_edge };
+            lots.push((Rect::new(x, y, w, h), north));
+            x += w + rng.range(2, 5);
+        }
+
+        lots
+    }
+
+    fn build(&mut self, kind: BuildingKind, walls: Rect, faces_south: bool, street_y: i32) {
+        for y in walls.y1 ..= walls.y2 {
+            for x in walls.x1 ..= walls.x2 {
+                let idx = self.map.xy_idx(x, y);
+                let on_edge = x == walls.x1 || x == walls.x2 || y == walls.y1 || y == walls.y2;
+                self.map.tiles[idx] = if on_edge { TileType::Wall } else { TileType::WoodFloor };
+            }
+        }
+
+        let (door_x, _) = walls.center();
+        let door_y = if faces_south { walls.y2 } else { walls.y1 };
+        let door = self.map.xy_idx(door_x, door_y);
+        self.map.tiles[door] = TileType::WoodFloor;
+
+        // a path from the door out to the street
+        let step = if faces_south { 1 } else { -1 };
+        let mut path_y = door_y + step;
+        while (path_y - street_y).abs() > 1 {
+            let idx = self.map.xy_idx(door_x, path_y);
+            self.map.tiles[idx] = TileType::Road;
+            path_y += step;
+        }
+
+        // posts along the near side of the street, either side of the path
+        if kind == BuildingKind::Saloon || kind == BuildingKind::SheriffsOffice {
+            for post_x in [door_x - 2, door_x + 2].iter() {
+                self.hitching_posts.push(self.map.xy_idx(*post_x, street_y - step));
+            }
+        }
+
+        self.buildings.push(TownBuilding {
+            kind,
+            rect: Rect::new(walls.x1, walls.y1, walls.x2 - walls.x1 - 1, walls.y2 - walls.y1 - 1),
+            door
+        });
+    }
+}
+
+impl MapBuilder for TownBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.map.outdoors = true;
+        self.lay_out_town(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        for building in self.buildings.iter() {
+            spawner::spawn_building(ecs, building);
+        }
+        for post in self.hitching_posts.iter() {
+            spawner::hitching_post(ecs, *post as i32 % self.map.width, *post as i32 / self.map.width);
+        }
+
+        // keep them up the street, clear of where the player rides in
+        let street: Vec<usize> = (0..self.map.tiles.len())
+            .filter(|idx| self.map.tiles[*idx] == TileType::Road && *idx as i32 % self.map.width > 20)
+            .filter(|idx| !self.hitching_posts.contains(idx))
+            .collect();
+        for _ in 0..WANDERERS {
+            spawner::spawn_wanderer(ecs, &street);
+        }
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rltk::{DijkstraMap, RandomNumberGenerator};
+    use super::{TownBuilder, BuildingKind};
+    use crate::map_builders::MapBuilder;
+    use crate::game_map::{TileType, MAPCOUNT};
+    use crate::snapshot::{assert_snapshot, map_to_text};
+
+    fn build(seed: u64) -> TownBuilder {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        let mut builder = TownBuilder::new(1);
+        builder.build_map(&mut rng);
+        builder
+    }
+
+    #[test]
+    fn town_matches_snapshot() {
+        let builder = build(7);
+        assert_snapshot("town_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn every_building_is_laid_out_once_with_a_door_off_the_street() {
+        let landmarks = [
+            BuildingKind::Saloon,
+            BuildingKind::SheriffsOffice,
+            BuildingKind::GeneralStore,
+            BuildingKind::Bank,
+            BuildingKind::Jail
+        ];
+
+        for seed in 0..10 {
+            let builder = build(seed);
+            let mut map = builder.get_map();
+            map.populate_blocked();
+            let start = builder.get_starting_position();
+            let distances = DijkstraMap::new(map.width, map.height, &[map.xy_idx(start.x, start.y)], &map, MAPCOUNT as f32);
+
+            for kind in landmarks.iter() {
+                let count = builder.buildings.iter().filter(|building| building.kind == *kind).count();
+                assert_eq!(count, 1, "seed {}: {:?}", seed, kind);
+            }
+
+            for building in builder.buildings.iter() {
+                let (x, y) = (building.door as i32 % map.width, building.door as i32 / map.width);
+                let rect = &building.rect;
+                let on_the_side = (x == rect.x1 || x == rect.x2 + 1) && y > rect.y1 && y <= rect.y2;
+                let on_the_front = (y == rect.y1 || y == rect.y2 + 1) && x > rect.x1 && x <= rect.x2;
+                assert!(on_the_side || on_the_front, "seed {}: {:?} door isn't in its wall", seed, building.kind);
+                assert!(distances.map[building.door] < f32::MAX, "seed {}: can't reach the {:?}", seed, building.kind);
+            }
+
+            assert_eq!(builder.hitching_posts.len(), 4);
+            assert!(builder.hitching_posts.iter().all(|post| map.tiles[*post] == TileType::Road), "seed {}", seed);
+        }
+    }
+}
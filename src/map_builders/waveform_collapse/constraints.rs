@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+use crate::game_map::TileType;
+
+
+pub const NORTH: usize = 0;
+pub const SOUTH: usize = 1;
+pub const WEST: usize = 2;
+pub const EAST: usize = 3;
+
+pub fn opposite(direction: usize) -> usize {
+    match direction {
+        NORTH => SOUTH,
+        SOUTH => NORTH,
+        WEST => EAST,
+        _ => WEST
+    }
+}
+
+
+/// One square pattern learned from the sample, plus which other patterns
+/// can sit next to it in each direction.
+pub struct MapChunk {
+    pub pattern: Vec<TileType>,
+    pub exits: [Vec<bool>; 4],
+    pub compatible_with: [Vec<usize>; 4]
+}
+
+
+/// Cuts the sample into `chunk_size` squares, adding mirrored copies so the
+/// output isn't locked to the sample's orientation.
+pub fn build_patterns(tiles: &[TileType], width: i32, height: i32, chunk_size: i32) -> Vec<Vec<TileType>> {
+    let mut patterns = Vec::new();
+    let mut seen = HashSet::new();
+
+    for cy in 0 .. height / chunk_size {
+        for cx in 0 .. width / chunk_size {
+            let mut base = Vec::new();
+            for y in 0..chunk_size {
+                for x in 0..chunk_size {
+                    let idx = ((cy * chunk_size + y) * width + cx * chunk_size + x) as usize;
+                    base.push(match tiles[idx] {
+                        TileType::DownStairs => TileType::Floor,
+                        tile => tile
+                    });
+                }
+            }
+
+            let flipped_x = flip(&base, chunk_size, true, false);
+            let flipped_y = flip(&base, chunk_size, false, true);
+            let flipped_both = flip(&base, chunk_size, true, true);
+            for pattern in [base, flipped_x, flipped_y, flipped_both] {
+                if seen.insert(pattern.clone()) {
+                    patterns.push(pattern);
+                }
+            }
+        }
+    }
+
+    patterns
+}
+
+fn flip(pattern: &[TileType], chunk_size: i32, horizontal: bool, vertical: bool) -> Vec<TileType> {
+    let mut flipped = Vec::with_capacity(pattern.len());
+    for y in 0..chunk_size {
+        for x in 0..chunk_size {
+            let sx = if horizontal { chunk_size - 1 - x } else { x };
+            let sy = if vertical { chunk_size - 1 - y } else { y };
+            flipped.push(pattern[(sy * chunk_size + sx) as usize]);
+        }
+    }
+    flipped
+}
+
+
+/// Two chunks can touch if their shared edge is closed on both sides, or if at
+/// least one open tile lines up so there's a way through.
+pub fn patterns_to_constraints(patterns: Vec<Vec<TileType>>, chunk_size: i32) -> Vec<MapChunk> {
+    let mut constraints: Vec<MapChunk> = patterns.into_iter()
+        .map(|pattern| {
+            let exits = edge_exits(&pattern, chunk_size);
+            MapChunk { pattern, exits, compatible_with: [Vec::new(), Vec::new(), Vec::new(), Vec::new()] }
+        })
+        .collect();
+
+    for i in 0..constraints.len() {
+        for j in 0..constraints.len() {
+            for direction in 0..4 {
+                if edges_fit(&constraints[i].exits[direction], &constraints[j].exits[opposite(direction)]) {
+                    constraints[i].compatible_with[direction].push(j);
+                }
+            }
+        }
+    }
+
+    constraints
+}
+
+fn edges_fit(ours: &[bool], theirs: &[bool]) -> bool {
+    let ours_closed = ours.iter().all(|open| !open);
+    let theirs_closed = theirs.iter().all(|open| !open);
+
+    if ours_closed || theirs_closed {
+        ours_closed && theirs_closed
+    } else {
+        ours.iter().zip(theirs.iter()).any(|(a, b)| *a && *b)
+    }
+}
+
+fn edge_exits(pattern: &[TileType], chunk_size: i32) -> [Vec<bool>; 4] {
+    let open = |x: i32, y: i32| pattern[(y * chunk_size + x) as usize] != TileType::Wall;
+
+    [
+        (0..chunk_size).map(|x| open(x, 0)).collect(),
+        (0..chunk_size).map(|x| open(x, chunk_size - 1)).collect(),
+        (0..chunk_size).map(|y| open(0, y)).collect(),
+        (0..chunk_size).map(|y| open(chunk_size - 1, y)).collect()
+    ]
+}
@@ -0,0 +1,283 @@
+use rltk::RandomNumberGenerator;
+use specs::prelude::*;
+use std::collections::BTreeMap;
+use super::MapBuilder;
+use super::common::{
+    remove_unreachable_areas_returning_most_distant, generate_voronoi_spawn_regions,
+    is_fully_connected, snapshot
+};
+use super::prefabs::{Prefab, glyph_to_tile};
+use crate::components::Position;
+use crate::game_map::{GameMap, TileType};
+use crate::spawner;
+
+mod constraints;
+mod solver;
+
+use constraints::{build_patterns, patterns_to_constraints};
+
+
+enum Sample {
+    PreviousMap,
+    Prefab(Prefab)
+}
+
+/// Learns which chunks of map sit next to each other in a sample, and grows a
+/// new level out of them. If the solver keeps painting itself into a corner the
+/// level falls back to whatever `previous` builds.
+pub struct WaveformCollapseBuilder {
+    previous: Box<dyn MapBuilder>,
+    sample: Sample,
+    chunk_size: i32,
+    map: GameMap,
+    starting_position: Position,
+    noise_areas: BTreeMap<i32, Vec<usize>>,
+    fell_back: bool,
+    history: Vec<GameMap>
+}
+
+impl WaveformCollapseBuilder {
+    /// Learns from the map `previous` generates.
+    pub fn derived_map(depth: i32, previous: Box<dyn MapBuilder>) -> WaveformCollapseBuilder {
+        WaveformCollapseBuilder::new(depth, previous, Sample::PreviousMap, 8)
+    }
+
+    /// Learns from a hand-drawn sample; `previous` is only built if that fails.
+    pub fn from_prefab(depth: i32, prefab: Prefab, previous: Box<dyn MapBuilder>) -> WaveformCollapseBuilder {
+        WaveformCollapseBuilder::new(depth, previous, Sample::Prefab(prefab), 3)
+    }
+
+    fn new(depth: i32, previous: Box<dyn MapBuilder>, sample: Sample, chunk_size: i32) -> WaveformCollapseBuilder {
+        WaveformCollapseBuilder {
+            previous,
+            sample,
+            chunk_size,
+            map: GameMap::new(depth),
+            starting_position: Position { x: 0, y: 0 },
+            noise_areas: BTreeMap::new(),
+            fell_back: false,
+            history: Vec::new()
+        }
+    }
+
+    fn sample_tiles(&mut self, rng: &mut RandomNumberGenerator) -> (Vec<TileType>, i32, i32) {
+        match &self.sample {
+            Sample::PreviousMap => {
+                self.previous.build_map(rng);
+                self.history = self.previous.get_snapshot_history();
+                let sample = self.previous.get_map();
+                (sample.tiles, sample.width, sample.height)
+            },
+            Sample::Prefab(prefab) => {
+                let mut tiles = Vec::with_capacity((prefab.width * prefab.height) as usize);
+                for y in 0..prefab.height {
+                    for x in 0..prefab.width {
+                        tiles.push(glyph_to_tile(prefab.get(x, y)).unwrap_or(TileType::Wall));
+                    }
+                }
+                (tiles, prefab.width, prefab.height)
+            }
+        }
+    }
+
+    fn collapse(&mut self, rng: &mut RandomNumberGenerator) {
+        const MAX_ATTEMPTS: i32 = 10;
+
+        let (tiles, width, height) = self.sample_tiles(rng);
+        let constraints = patterns_to_constraints(build_patterns(&tiles, width, height, self.chunk_size), self.chunk_size);
+
+        let chunks_x = self.map.width / self.chunk_size;
+        let chunks_y = self.map.height / self.chunk_size;
+
+        for _ in 0..MAX_ATTEMPTS {
+            let chunks = match solver::solve(&constraints, chunks_x, chunks_y, rng) {
+                None => continue,
+                Some(chunks) => chunks
+            };
+
+            let mut map = GameMap::new(self.map.depth);
+            for (slot, chunk) in chunks.iter().enumerate() {
+                let left = (slot as i32 % chunks_x) * self.chunk_size;
+                let top = (slot as i32 / chunks_x) * self.chunk_size;
+                for y in 0..self.chunk_size {
+                    for x in 0..self.chunk_size {
+                        let idx = map.xy_idx(left + x, top + y);
+                        map.tiles[idx] = constraints[*chunk].pattern[(y * self.chunk_size + x) as usize];
+                    }
+                }
+            }
+
+            self.history.push(snapshot(&map));
+            if let Some(start) = self.accept(&mut map) {
+                self.map = map;
+                self.history.push(snapshot(&self.map));
+                self.starting_position = start;
+                self.noise_areas = generate_voronoi_spawn_regions(&self.map, rng);
+                return;
+            }
+        }
+
+        self.fall_back(rng);
+    }
+
+    /// Seals the edges, keeps the part reachable from the middle and checks
+    /// there's enough of it left to be worth playing.
+    fn accept(&self, map: &mut GameMap) -> Option<Position> {
+        const MIN_FLOOR_FRACTION: f32 = 0.25;
+
+        for x in 0..map.width {
+            let top = map.xy_idx(x, 0);
+            let bottom = map.xy_idx(x, map.height - 1);
+            map.tiles[top] = TileType::Wall;
+            map.tiles[bottom] = TileType::Wall;
+        }
+        for y in 0..map.height {
+            let left = map.xy_idx(0, y);
+            let right = map.xy_idx(map.width - 1, y);
+            map.tiles[left] = TileType::Wall;
+            map.tiles[right] = TileType::Wall;
+        }
+
+        let (center_x, center_y) = (map.width / 2, map.height / 2);
+        let start_idx = (0..map.tiles.len())
+            .filter(|idx| map.tiles[*idx] != TileType::Wall)
+            .min_by_key(|idx| {
+                (*idx as i32 % map.width - center_x).abs() + (*idx as i32 / map.width - center_y).abs()
+            })?;
+
+        let exit_idx = remove_unreachable_areas_returning_most_distant(map, start_idx);
+        let floor = map.tiles.iter().filter(|tile| **tile != TileType::Wall).count();
+        if exit_idx == start_idx || (floor as f32) < map.tiles.len() as f32 * MIN_FLOOR_FRACTION {
+            return None;
+        }
+
+        map.tiles[exit_idx] = TileType::DownStairs;
+        if !is_fully_connected(map, start_idx) {
+            return None;
+        }
+
+        Some(Position { x: start_idx as i32 % map.width, y: start_idx as i32 / map.width })
+    }
+
+    fn fall_back(&mut self, rng: &mut RandomNumberGenerator) {
+        if let Sample::Prefab(_) = self.sample {
+            self.previous.build_map(rng);
+            self.history.append(&mut self.previous.get_snapshot_history());
+        }
+        self.map = self.previous.get_map();
+        self.history.push(snapshot(&self.map));
+        self.starting_position = self.previous.get_starting_position();
+        self.fell_back = true;
+    }
+}
+
+impl MapBuilder for WaveformCollapseBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.collapse(rng);
+    }
+
+    fn spawn_entities(&mut self, ecs: &mut World) {
+        if self.fell_back {
+            self.previous.spawn_entities(ecs);
+            return;
+        }
+
+        let start_idx = self.map.xy_idx(self.starting_position.x, self.starting_position.y);
+        for area in self.noise_areas.values() {
+            if !area.contains(&start_idx) {
+                spawner::spawn_region(ecs, area);
+            }
+        }
+    }
+
+    fn get_map(&self) -> GameMap {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position.clone()
+    }
+    fn get_snapshot_history(&self) -> Vec<GameMap> {
+        self.history.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::bsp_dungeon::BspDungeonBuilder;
+    use crate::map_builders::cellular_automata::CellularAutomataBuilder;
+    use crate::map_builders::simple_map::SimpleMapBuilder;
+    use crate::map_builders::prefabs::CELLAR_SAMPLE;
+    use crate::snapshot::{assert_snapshot, map_to_text};
+
+    fn build(mut builder: WaveformCollapseBuilder, seed: u64) -> WaveformCollapseBuilder {
+        let mut rng = RandomNumberGenerator::seeded(seed);
+        builder.build_map(&mut rng);
+        builder
+    }
+
+    fn assert_playable(builder: &WaveformCollapseBuilder, what: &str) {
+        let mut map = builder.get_map();
+        let start = builder.get_starting_position();
+        let start_idx = map.xy_idx(start.x, start.y);
+        assert_ne!(map.tiles[start_idx], TileType::Wall, "{} starts inside a wall", what);
+        assert!(is_fully_connected(&mut map, start_idx), "{} left unreachable floor", what);
+        assert!(map.tiles.contains(&TileType::DownStairs), "{} has no way down", what);
+    }
+
+    #[test]
+    fn cellar_matches_snapshot() {
+        let builder = build(WaveformCollapseBuilder::from_prefab(3, CELLAR_SAMPLE.load(), Box::new(SimpleMapBuilder::new(3))), 7);
+        assert!(!builder.fell_back);
+        assert_snapshot("waveform_cellar_seed_7", &map_to_text(&builder.get_map()));
+    }
+
+    #[test]
+    fn every_sample_is_connected() {
+        for seed in 0..5 {
+            let builder = build(WaveformCollapseBuilder::derived_map(2, Box::new(BspDungeonBuilder::new(2))), seed);
+            assert_playable(&builder, &format!("bsp sample, seed {}", seed));
+            let builder = build(WaveformCollapseBuilder::derived_map(4, Box::new(CellularAutomataBuilder::new(4))), seed);
+            assert_playable(&builder, &format!("cave sample, seed {}", seed));
+            let builder = build(WaveformCollapseBuilder::from_prefab(3, CELLAR_SAMPLE.load(), Box::new(SimpleMapBuilder::new(3))), seed);
+            assert_playable(&builder, &format!("cellar sample, seed {}", seed));
+        }
+    }
+
+    #[test]
+    fn a_sample_too_small_to_learn_from_falls_back_to_the_previous_builder() {
+        // smaller than a single chunk, so there are no patterns and every slot is a contradiction
+        let tiny = Prefab::from_rows(&["..", ".."]);
+        let builder = build(WaveformCollapseBuilder::from_prefab(3, tiny, Box::new(SimpleMapBuilder::new(3))), 1);
+        assert!(builder.fell_back);
+        assert_playable(&builder, "the fallback");
+        assert_eq!(builder.get_snapshot_history().last().unwrap().tiles, builder.get_map().tiles);
+    }
+
+    #[test]
+    fn accepting_a_map_walls_off_what_cant_be_reached() {
+        let builder = WaveformCollapseBuilder::derived_map(2, Box::new(SimpleMapBuilder::new(2)));
+        let mut map = GameMap::new(2);
+        for y in 5..40 {
+            for x in 10..70 {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            }
+        }
+        let island = map.xy_idx(3, 3);
+        map.tiles[island] = TileType::Floor;
+
+        let start = builder.accept(&mut map).expect("a big open room is fine");
+        assert_eq!((start.x, start.y), (map.width / 2, map.height / 2));
+        assert_eq!(map.tiles[island], TileType::Wall);
+        assert_eq!(map.tiles.iter().filter(|tile| **tile == TileType::DownStairs).count(), 1);
+
+        let mut cramped = GameMap::new(2);
+        for x in 10..20 {
+            let idx = cramped.xy_idx(x, 20);
+            cramped.tiles[idx] = TileType::Floor;
+        }
+        assert!(builder.accept(&mut cramped).is_none(), "too little floor to play on");
+    }
+}
@@ -0,0 +1,94 @@
+use rltk::RandomNumberGenerator;
+use super::constraints::{MapChunk, NORTH, SOUTH, WEST, EAST, opposite};
+
+
+const NEIGHBOURS: [(usize, i32, i32); 4] = [(NORTH, 0, -1), (SOUTH, 0, 1), (WEST, -1, 0), (EAST, 1, 0)];
+
+
+/// Fills a grid of chunk slots one at a time, always collapsing the slot with
+/// the fewest options left. When a slot runs out of options its neighbours are
+/// pulled back up and retried; `None` means that didn't work out either.
+pub fn solve(
+    constraints: &[MapChunk],
+    chunks_x: i32,
+    chunks_y: i32,
+    rng: &mut RandomNumberGenerator
+) -> Option<Vec<usize>> {
+    let mut placed: Vec<Option<usize>> = vec![None; (chunks_x * chunks_y) as usize];
+    let max_resets = placed.len() * 4;
+    let mut resets = 0;
+
+    while placed.iter().any(Option::is_none) {
+        let mut best: Option<(usize, Vec<usize>)> = None;
+        let mut stuck: Option<usize> = None;
+
+        for (slot, chunk) in placed.iter().enumerate() {
+            if chunk.is_some() {
+                continue;
+            }
+
+            let options = options_for(constraints, &placed, slot as i32, chunks_x, chunks_y);
+            if options.is_empty() {
+                stuck = Some(slot);
+                break;
+            }
+
+            let fewer = match &best {
+                None => true,
+                Some((_, best_options)) => {
+                    options.len() < best_options.len()
+                        || (options.len() == best_options.len() && rng.roll_dice(1, 2) == 1)
+                }
+            };
+            if fewer {
+                best = Some((slot, options));
+            }
+        }
+
+        if let Some(slot) = stuck {
+            resets += 1;
+            if resets > max_resets {
+                return None;
+            }
+            for neighbour in neighbours(slot as i32, chunks_x, chunks_y) {
+                placed[neighbour] = None;
+            }
+            continue;
+        }
+
+        let (slot, options) = best?;
+        placed[slot] = Some(*rng.random_slice_entry(&options).unwrap());
+    }
+
+    placed.into_iter().collect()
+}
+
+fn neighbours(slot: i32, chunks_x: i32, chunks_y: i32) -> Vec<usize> {
+    let x = slot % chunks_x;
+    let y = slot / chunks_x;
+
+    NEIGHBOURS.iter()
+        .map(|(_, dx, dy)| (x + dx, y + dy))
+        .filter(|(nx, ny)| *nx >= 0 && *nx < chunks_x && *ny >= 0 && *ny < chunks_y)
+        .map(|(nx, ny)| (ny * chunks_x + nx) as usize)
+        .collect()
+}
+
+fn options_for(constraints: &[MapChunk], placed: &[Option<usize>], slot: i32, chunks_x: i32, chunks_y: i32) -> Vec<usize> {
+    let mut options: Vec<usize> = (0..constraints.len()).collect();
+
+    for (direction, dx, dy) in NEIGHBOURS.iter() {
+        let nx = slot % chunks_x + dx;
+        let ny = slot / chunks_x + dy;
+        if nx < 0 || nx >= chunks_x || ny < 0 || ny >= chunks_y {
+            continue;
+        }
+
+        if let Some(neighbour) = placed[(ny * chunks_x + nx) as usize] {
+            let allowed = &constraints[neighbour].compatible_with[opposite(*direction)];
+            options.retain(|option| allowed.contains(option));
+        }
+    }
+
+    options
+}
@@ -1,32 +1,49 @@
 use specs::prelude::*;
-use crate::components::{CombatStats, WantsToMelee, Name, SufferDamage};
-use crate::game_log::GameLog;
+use crate::components::{CombatStats, WantsToMelee, Name, SufferDamage, Renderable};
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::options::Options;
 
 pub struct MeleeCombatSystem;
 
 impl<'a> System<'a> for MeleeCombatSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = ( WriteExpect<'a, GameLog>,
+                        ReadExpect<'a, Options>,
+                        ReadExpect<'a, Entity>,
                         Entities<'a>,
                         WriteStorage<'a, WantsToMelee>,
                         ReadStorage<'a, Name>,
+                        ReadStorage<'a, Renderable>,
                         ReadStorage<'a, CombatStats>,
                         WriteStorage<'a, SufferDamage> );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut log, entities, mut wants_melee, names, combat_stats, mut inflict_damage) = data;
+        let (mut log, options, player_entity, entities, mut wants_melee, names, renderables, combat_stats, mut inflict_damage) = data;
 
-        for (_entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
+        for (entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
             if stats.hp > 0 {
                 let target_stats = combat_stats.get(wants_melee.target).unwrap();
                 let target_name = names.get(wants_melee.target).unwrap();
-                let damage = i32::max(0, stats.power - target_stats.defense);
+                let mut damage = i32::max(0, stats.power - target_stats.defense);
+                if wants_melee.target == *player_entity {
+                    damage = options.difficulty.damage_taken(damage);
+                }
 
+                let message = LogBuilder::new(LogCategory::Combat)
+                    .name(&name.name, renderables.get(entity));
                 if damage == 0 {
-                    log.entries.insert(0, format!("{} is unable to hurt {}", &name.name, &target_name.name));
+                    message.text(" is unable to hurt ")
+                        .name(&target_name.name, renderables.get(wants_melee.target))
+                        .minor()
+                        .log(&mut log);
                 } else {
-                    log.entries.insert(0, format!("{} hits {}, for {} hp.", &name.name, &target_name.name, damage));
-                    inflict_damage.insert(wants_melee.target, SufferDamage { amount: damage }).expect("Unable to do damage");
+                    message.text(" hits ")
+                        .name(&target_name.name, renderables.get(wants_melee.target))
+                        .text(", for ")
+                        .damage(damage)
+                        .text(".")
+                        .log(&mut log);
+                    SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
                 }
             }
         }
@@ -0,0 +1,72 @@
+use specs::prelude::*;
+use crate::game_map::GameMap;
+use crate::components::{Position, Renderable, Player, Hidden};
+
+/// Remembers what the player can currently see on each visible tile, and forgets
+/// whatever used to be on visible tiles that are now empty.
+pub struct EntityMemorySystem;
+
+impl<'a> System<'a> for EntityMemorySystem {
+    type SystemData = ( WriteExpect<'a, GameMap>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Renderable>,
+                        ReadStorage<'a, Player>,
+                        ReadStorage<'a, Hidden> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, positions, renderables, players, hidden) = data;
+
+        for idx in 0..map.remembered_glyphs.len() {
+            if map.visible_tiles[idx] {
+                map.remembered_glyphs[idx] = None;
+            }
+        }
+
+        // remember what is drawn on top, which is whatever has the lowest render order
+        let mut seen: Vec<(&Position, &Renderable)> = (&positions, &renderables, !&players, !&hidden).join()
+            .map(|(position, render, _not_player, _not_hidden)| (position, render))
+            .collect();
+        seen.sort_by_key(|(_, render)| -render.render_order);
+        for (position, render) in seen {
+            let idx = map.xy_idx(position.x, position.y);
+            if map.visible_tiles[idx] {
+                map.remembered_glyphs[idx] = Some((render.glyph, render.fg));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw_map;
+    use crate::tileset::Tileset;
+    use crate::frame_buffer::FrameBuffer;
+    use crate::snapshot::{fixture_world, named};
+
+    fn glyph_at(ecs: &World, x: i32, y: i32) -> char {
+        let mut frame = FrameBuffer::new(80, 50);
+        draw_map(&ecs.fetch::<GameMap>(), &ecs.fetch::<Tileset>(), &mut frame);
+        rltk::to_char(frame.get(x, y).glyph)
+    }
+
+    #[test]
+    fn monsters_are_remembered_out_of_sight_until_seen_again() {
+        let ecs = fixture_world();
+        EntityMemorySystem.run_now(&ecs);
+
+        let orc_tile = ecs.fetch::<GameMap>().xy_idx(12, 10);
+        ecs.fetch_mut::<GameMap>().visible_tiles[orc_tile] = false;
+        assert_eq!(glyph_at(&ecs, 12, 10), 'o');
+
+        ecs.write_storage::<Position>().get_mut(named(&ecs, "Orc")).unwrap().x = 25;
+        EntityMemorySystem.run_now(&ecs);
+        assert_eq!(glyph_at(&ecs, 12, 10), 'o', "an unseen tile keeps its memory");
+
+        ecs.fetch_mut::<GameMap>().visible_tiles[orc_tile] = true;
+        EntityMemorySystem.run_now(&ecs);
+        ecs.fetch_mut::<GameMap>().visible_tiles[orc_tile] = false;
+        assert_eq!(glyph_at(&ecs, 12, 10), '#', "seeing the tile empty forgets the orc");
+        assert_eq!(glyph_at(&ecs, 25, 10), '#', "the orc walked off where nobody saw it");
+    }
+}
@@ -1,9 +1,12 @@
 use specs::prelude::*;
-use crate::components::{Viewshed, WantsToMelee, Monster, Position};
+use crate::components::{Viewshed, WantsToMelee, Monster, Position, Door, WantsToOpenDoor, Immobilized, EntityMoved, Tracking};
 use crate::game_map::GameMap;
+use crate::weather::WeatherState;
 use super::RunState;
 use rltk::Point;
 
+/// Monsters go for the player while they can see them. Out in the open they then
+/// follow the player's tracks to where they were last seen for a while.
 pub struct MonsterAI;
 
 impl<'a> System<'a> for MonsterAI {
@@ -13,11 +16,17 @@ impl<'a> System<'a> for MonsterAI {
                         ReadExpect<'a, Point>, // player position
                         ReadExpect<'a, RunState>,
                         ReadExpect<'a, Entity>, // player
+                        ReadExpect<'a, WeatherState>,
                         Entities<'a>,
                         ReadStorage<'a, Monster>,
                         WriteStorage<'a, Viewshed>,
                         WriteStorage<'a, Position>,
-                        WriteStorage<'a, WantsToMelee> );
+                        WriteStorage<'a, WantsToMelee>,
+                        ReadStorage<'a, Door>,
+                        WriteStorage<'a, WantsToOpenDoor>,
+                        ReadStorage<'a, Immobilized>,
+                        WriteStorage<'a, EntityMoved>,
+                        WriteStorage<'a, Tracking> );
 
     fn run(&mut self, data: Self::SystemData) {
         let (
@@ -25,39 +34,120 @@ impl<'a> System<'a> for MonsterAI {
             player_pos,
             run_state,
             player_entity,
+            weather,
             entities,
             monsters,
             mut viewsheds,
             mut positions,
-            mut wants_to_melee
+            mut wants_to_melee,
+            doors,
+            mut wants_to_open,
+            immobilized,
+            mut moved,
+            mut tracking
         ) = data;
 
         if *run_state == RunState::MonsterTurn {
-            for (entity, _monster, mut viewshed, mut pos) in (&entities, &monsters, &mut viewsheds, &mut positions).join() {
+            let mut lost = Vec::new();
+            for (entity, _monster, viewshed, pos) in (&entities, &monsters, &mut viewsheds, &mut positions).join() {
                 let distance_to_player = rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
                 if distance_to_player < 1.5 {
                     wants_to_melee.insert(entity, WantsToMelee { target: *player_entity }).expect("Unable to attack player");
-                } else if viewshed.visible_tiles.contains(&*player_pos) {
-                    let path = rltk::a_star_search(
-                        map.xy_idx(pos.x, pos.y),
-                        map.xy_idx(player_pos.x, player_pos.y),
-                        &*map
-                    );
-
-                    if path.success && path.steps.len() > 1 {
-                        let mut idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked[idx] = false;
-
-                        pos.x = (path.steps[1] as i32) % map.width;
-                        pos.y = (path.steps[1] as i32) / map.width;
-                        idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked[idx] = true;
-
-                        viewshed.dirty = true;
+                    continue;
+                } else if immobilized.contains(entity) {
+                    continue;
+                }
+
+                let target = if viewshed.visible_tiles.contains(&*player_pos) {
+                    if map.outdoors {
+                        let turns_left = weather.weather.tracking_turns();
+                        tracking.insert(entity, Tracking { last_seen: *player_pos, turns_left })
+                            .expect("Unable to track player");
+                    }
+                    *player_pos
+                } else if let Some(tracks) = tracking.get_mut(entity) {
+                    tracks.turns_left -= 1;
+                    if tracks.turns_left <= 0 || tracks.last_seen == Point::new(pos.x, pos.y) {
+                        lost.push(entity);
+                        continue;
+                    }
+                    tracks.last_seen
+                } else {
+                    continue;
+                };
+
+                let path = rltk::a_star_search(
+                    map.xy_idx(pos.x, pos.y),
+                    map.xy_idx(target.x, target.y),
+                    &*map
+                );
+
+                if path.success && path.steps.len() > 1 && map.openable_doors[path.steps[1]] {
+                    // spend the turn opening the door in the way
+                    let door = map.tile_content[path.steps[1]].iter().find(|entity| doors.contains(**entity));
+                    if let Some(door) = door {
+                        wants_to_open.insert(entity, WantsToOpenDoor { door: *door }).expect("Unable to open door");
                     }
+                } else if path.success && path.steps.len() > 1 {
+                    let mut idx = map.xy_idx(pos.x, pos.y);
+                    map.blocked[idx] = false;
+
+                    pos.x = (path.steps[1] as i32) % map.width;
+                    pos.y = (path.steps[1] as i32) / map.width;
+                    idx = map.xy_idx(pos.x, pos.y);
+                    map.blocked[idx] = true;
+
+                    viewshed.dirty = true;
+                    moved.insert(entity, EntityMoved).expect("Unable to insert marker");
                 }
             }
+
+            for entity in lost {
+                tracking.remove(entity);
+            }
         }
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weather::{Weather, TRACKING_TURNS};
+    use crate::snapshot::{fixture_world, named};
+
+    /// Lets the orc at the end of the corridor glimpse the player once, then runs
+    /// monster turns with the player out of sight, and says where the orc gave up.
+    fn where_the_orc_gives_up(outdoors: bool, weather: Weather) -> i32 {
+        let mut ecs = fixture_world();
+        ecs.fetch_mut::<GameMap>().outdoors = outdoors;
+        ecs.insert(WeatherState { weather, turns_left: 100 });
+        ecs.insert(RunState::MonsterTurn);
+        let orc = named(&ecs, "Orc");
+        *ecs.write_storage::<Position>().get_mut(orc).unwrap() = Position { x: 30, y: 10 };
+        ecs.write_storage::<Viewshed>().get_mut(orc).unwrap().visible_tiles = vec![Point::new(10, 10)];
+
+        MonsterAI.run_now(&ecs);
+        assert_eq!(ecs.read_storage::<Position>().get(orc).unwrap().x, 29);
+        ecs.write_storage::<Viewshed>().get_mut(orc).unwrap().visible_tiles.clear();
+        for _ in 0..20 {
+            MonsterAI.run_now(&ecs);
+        }
+        assert!(!ecs.read_storage::<Tracking>().contains(orc));
+        let x = ecs.read_storage::<Position>().get(orc).unwrap().x;
+        x
+    }
+
+    #[test]
+    fn monsters_follow_tracks_for_a_while_and_rain_washes_them_out() {
+        let clear = where_the_orc_gives_up(true, Weather::Clear);
+        assert_eq!(clear, 29 - (TRACKING_TURNS - 1));
+        let rain = where_the_orc_gives_up(true, Weather::Rain);
+        assert!(rain > clear, "the orc lost the trail at {} in the rain, {} in the dry", rain, clear);
+    }
+
+    #[test]
+    fn underground_monsters_stop_as_soon_as_they_lose_sight() {
+        assert_eq!(where_the_orc_gives_up(false, Weather::Clear), 29);
+    }
+}
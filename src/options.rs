@@ -0,0 +1,201 @@
+/// Where the player's options are kept, relative to the working directory.
+pub const OPTIONS_FILE: &str = "options.cfg";
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Font {
+    Terminal8x8,
+    Vga8x16
+}
+
+/// How much the message log records: terse keeps only what matters most, verbose
+/// keeps even the misses.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+pub enum Verbosity {
+    Terse,
+    Normal,
+    Verbose
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard
+}
+
+impl Font {
+    pub const ALL: [Font; 2] = [Font::Terminal8x8, Font::Vga8x16];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Font::Terminal8x8 => "terminal8x8",
+            Font::Vga8x16 => "vga8x16"
+        }
+    }
+}
+
+impl Verbosity {
+    pub const ALL: [Verbosity; 3] = [Verbosity::Terse, Verbosity::Normal, Verbosity::Verbose];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Verbosity::Terse => "terse",
+            Verbosity::Normal => "normal",
+            Verbosity::Verbose => "verbose"
+        }
+    }
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard"
+        }
+    }
+
+    /// Scales damage done to the player.
+    pub fn damage_taken(self, damage: i32) -> i32 {
+        match self {
+            Difficulty::Easy => (damage + 1) / 2,
+            Difficulty::Normal => damage,
+            Difficulty::Hard => damage + (damage + 1) / 2
+        }
+    }
+}
+
+/// The next value along, wrapping around at the end.
+pub fn cycle<T: PartialEq + Copy>(all: &[T], current: T, forward: bool) -> T {
+    let idx = all.iter().position(|value| *value == current).unwrap_or(0);
+    let next = if forward { idx + 1 } else { idx + all.len() - 1 };
+    all[next % all.len()]
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub title: String,
+    pub font: Font,
+    pub scanlines: bool,
+    /// Draw the map with sprites from the tileset instead of glyphs.
+    pub tiles: bool,
+    pub verbosity: Verbosity,
+    pub difficulty: Difficulty
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            title: "Hello bitches".to_string(),
+            font: Font::Terminal8x8,
+            scanlines: true,
+            tiles: false,
+            verbosity: Verbosity::Normal,
+            difficulty: Difficulty::Normal
+        }
+    }
+}
+
+fn parse_choice<T: Copy>(all: &[T], name: fn(T) -> &'static str, value: &str) -> Option<T> {
+    all.iter().copied().find(|choice| name(*choice) == value)
+}
+
+impl Options {
+    /// Reads `option = value` lines. Anything that can't be understood is reported
+    /// and left at its default.
+    pub fn parse(text: &str) -> (Options, Vec<String>) {
+        let mut options = Options::default();
+        let mut errors = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => {
+                    errors.push(format!("line {}: expected `option = value`", number + 1));
+                    continue;
+                }
+            };
+
+            let understood = match name {
+                "title" => {
+                    options.title = value.to_string();
+                    true
+                },
+                "font" => parse_choice(&Font::ALL, Font::name, value).map(|font| options.font = font).is_some(),
+                "scanlines" => value.parse().map(|scanlines| options.scanlines = scanlines).is_ok(),
+                "tiles" => value.parse().map(|tiles| options.tiles = tiles).is_ok(),
+                "verbosity" => parse_choice(&Verbosity::ALL, Verbosity::name, value).map(|verbosity| options.verbosity = verbosity).is_some(),
+                "difficulty" => parse_choice(&Difficulty::ALL, Difficulty::name, value).map(|difficulty| options.difficulty = difficulty).is_some(),
+                _ => {
+                    errors.push(format!("line {}: unknown option `{}`", number + 1, name));
+                    continue;
+                }
+            };
+            if !understood {
+                errors.push(format!("line {}: `{}` isn't a valid {}", number + 1, value, name));
+            }
+        }
+        (options, errors)
+    }
+
+    pub fn to_config(&self) -> String {
+        format!(
+            "title = {}\nfont = {}\nscanlines = {}\ntiles = {}\nverbosity = {}\ndifficulty = {}\n",
+            self.title, self.font.name(), self.scanlines, self.tiles, self.verbosity.name(), self.difficulty.name()
+        )
+    }
+
+    /// Loads the options file, falling back to the defaults if there isn't one.
+    pub fn load() -> (Options, Vec<String>) {
+        match std::fs::read_to_string(OPTIONS_FILE) {
+            Ok(text) => Options::parse(&text),
+            Err(_) => (Options::default(), Vec::new())
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::write(OPTIONS_FILE, self.to_config())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_survive_a_round_trip_through_the_file() {
+        let options = Options {
+            title: "Dust and Lead".to_string(),
+            font: Font::Vga8x16,
+            scanlines: false,
+            tiles: true,
+            verbosity: Verbosity::Verbose,
+            difficulty: Difficulty::Hard
+        };
+        let (parsed, errors) = Options::parse(&options.to_config());
+        assert!(errors.is_empty());
+        assert_eq!(parsed.to_config(), options.to_config());
+    }
+
+    #[test]
+    fn bad_lines_are_reported_and_left_at_their_defaults() {
+        let (options, errors) = Options::parse("font = comic_sans\nscanlines = false\nvolume = 11\n");
+        assert_eq!(options.font, Font::Terminal8x8);
+        assert!(!options.scanlines);
+        assert_eq!(errors, vec!["line 1: `comic_sans` isn't a valid font", "line 3: unknown option `volume`"]);
+    }
+
+    #[test]
+    fn difficulty_scales_damage_to_the_player() {
+        assert_eq!(Difficulty::Easy.damage_taken(5), 3);
+        assert_eq!(Difficulty::Normal.damage_taken(5), 5);
+        assert_eq!(Difficulty::Hard.damage_taken(5), 8);
+        assert_eq!(Difficulty::Easy.damage_taken(0), 0);
+    }
+}
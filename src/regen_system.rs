@@ -0,0 +1,77 @@
+use specs::prelude::*;
+use crate::game_map::GameMap;
+use crate::components::{CombatStats, HealthRegen, Monster, Position};
+use super::RunState;
+
+/// Counts down to the next regenerated hit point on every player turn. The count
+/// stands still while any monster is in view.
+pub struct RegenSystem;
+
+impl<'a> System<'a> for RegenSystem {
+    type SystemData = ( ReadExpect<'a, GameMap>,
+                        ReadExpect<'a, RunState>,
+                        ReadStorage<'a, Monster>,
+                        ReadStorage<'a, Position>,
+                        WriteStorage<'a, HealthRegen>,
+                        WriteStorage<'a, CombatStats> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (map, run_state, monsters, positions, mut regens, mut stats) = data;
+
+        if *run_state != RunState::PlayerTurn {
+            return;
+        }
+        let hostile_in_view = (&monsters, &positions).join()
+            .any(|(_monster, pos)| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
+        if hostile_in_view {
+            return;
+        }
+
+        for (regen, stats) in (&mut regens, &mut stats).join() {
+            if stats.hp >= stats.max_hp {
+                regen.turns_left = regen.turns_per_hp;
+                continue;
+            }
+            regen.turns_left -= 1;
+            if regen.turns_left <= 0 {
+                stats.hp += 1;
+                regen.turns_left = regen.turns_per_hp;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::{fixture_world, named, player_hp};
+
+    #[test]
+    fn heals_over_turns_only_with_no_monster_in_view() {
+        let mut ecs = fixture_world();
+        ecs.insert(RunState::PlayerTurn);
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<CombatStats>().get_mut(player).unwrap().hp = 20;
+        let turns_per_hp = ecs.read_storage::<HealthRegen>().get(player).unwrap().turns_per_hp;
+
+        for _ in 0..turns_per_hp * 2 {
+            RegenSystem.run_now(&ecs);
+        }
+        assert_eq!(player_hp(&ecs), 20, "the orc is in view");
+
+        let orc = named(&ecs, "Orc");
+        ecs.write_storage::<Position>().get_mut(orc).unwrap().x = 26;
+        for _ in 0..turns_per_hp * 2 {
+            RegenSystem.run_now(&ecs);
+        }
+        assert_eq!(player_hp(&ecs), 22);
+
+        *ecs.write_resource::<RunState>() = RunState::MonsterTurn;
+        RegenSystem.run_now(&ecs);
+        ecs.insert(RunState::PlayerTurn);
+        for _ in 0..turns_per_hp * 20 {
+            RegenSystem.run_now(&ecs);
+        }
+        assert_eq!(player_hp(&ecs), 30);
+    }
+}
@@ -0,0 +1,164 @@
+//! Golden text files for tests. Run the tests with `UPDATE_SNAPSHOTS=1` set
+//! to write out new snapshots after an intentional change.
+use rltk::{Point, RGB, RandomNumberGenerator};
+use specs::prelude::*;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use crate::components::{Name, Item, Potion, InBackpack, CombatStats};
+use crate::frame_buffer::FrameBuffer;
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::game_map::{GameMap, TileType};
+use crate::clock::GameClock;
+use crate::weather::WeatherState;
+use crate::visibility_system::SpotChecks;
+use crate::keymap::Keymap;
+use crate::options::Options;
+use crate::tileset::Tileset;
+use crate::spawner;
+
+
+pub fn assert_snapshot(name: &str, actual: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("snapshots").join(format!("{}.txt", name));
+
+    if std::env::var("UPDATE_SNAPSHOTS").is_ok() {
+        fs::create_dir_all(path.parent().unwrap()).expect("Unable to create snapshot directory");
+        fs::write(&path, actual).expect("Unable to write snapshot");
+        return;
+    }
+
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Missing snapshot {}, run with UPDATE_SNAPSHOTS=1 to create it", path.display()));
+    assert!(expected == actual, "Snapshot {} changed.\nExpected:\n{}\nActual:\n{}", name, expected, actual);
+}
+
+pub fn map_to_text(map: &GameMap) -> String {
+    let mut text = String::new();
+    for y in 0..map.height {
+        for x in 0..map.width {
+            text.push(match map.tiles[map.xy_idx(x, y)] {
+                TileType::Wall => '#',
+                TileType::Floor => '.',
+                TileType::DownStairs => '>',
+                TileType::Sand => ',',
+                TileType::Road => '=',
+                TileType::WoodFloor => '_'
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// The frame's glyphs, then the same grid with a key letter for each fg/bg pair,
+/// then what each key means.
+pub fn frame_to_text(frame: &FrameBuffer) -> String {
+    const KEYS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let hex = |c: RGB| format!("{:02x}{:02x}{:02x}", (c.r * 255.0) as u8, (c.g * 255.0) as u8, (c.b * 255.0) as u8);
+
+    let mut glyphs = String::new();
+    let mut colors = String::new();
+    let mut legend: Vec<String> = Vec::new();
+    let mut keys: HashMap<String, char> = HashMap::new();
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let cell = frame.get(x, y);
+            glyphs.push(rltk::to_char(cell.glyph));
+
+            let pair = format!("fg {} bg {}", hex(cell.fg), hex(cell.bg));
+            let key = *keys.entry(pair.clone()).or_insert_with(|| {
+                let key = KEYS.chars().nth(legend.len()).unwrap_or('?');
+                legend.push(format!("{} {}", key, pair));
+                key
+            });
+            colors.push(key);
+        }
+        glyphs.push('\n');
+        colors.push('\n');
+    }
+    format!("{}\n{}\n{}\n", glyphs, colors, legend.join("\n"))
+}
+
+/// A small hand built level at depth 2: a room in view holding the player, an orc,
+/// a potion and the stairs, with a remembered corridor running east out of it,
+/// two potions in the backpack and a few lines in the log.
+pub fn fixture_world() -> World {
+    let mut ecs = World::new();
+    crate::register_components(&mut ecs);
+    ecs.insert(RandomNumberGenerator::seeded(1));
+    ecs.insert(GameClock::default());
+    ecs.insert(WeatherState::default());
+    ecs.insert(SpotChecks::default());
+
+    let mut map = GameMap::new(2);
+    for y in 5..=15 {
+        for x in 5..=20 {
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+    for x in 21..=30 {
+        let idx = map.xy_idx(x, 10);
+        map.tiles[idx] = TileType::Floor;
+    }
+    let stairs = map.xy_idx(18, 13);
+    map.tiles[stairs] = TileType::DownStairs;
+    for y in 4..=16 {
+        for x in 4..=31 {
+            let idx = map.xy_idx(x, y);
+            map.revealed_tiles[idx] = true;
+            map.visible_tiles[idx] = x <= 21;
+        }
+    }
+    map.populate_blocked();
+    map.ambient_light = 1.0;
+    map.light = vec![1.0; map.light.len()];
+    ecs.insert(map);
+
+    let player = spawner::player(&mut ecs, 10, 10);
+    ecs.insert(player);
+    ecs.insert(Point::new(10, 10));
+    spawner::spawn_entity(&mut ecs, 12, 10, "Orc");
+    spawner::spawn_entity(&mut ecs, 14, 12, "Health Potion");
+    for _ in 0..2 {
+        ecs.create_entity()
+            .with(Name { name: "Health Potion".to_string() })
+            .with(Item)
+            .with(Potion { heal_amount: 8 })
+            .with(InBackpack { owner: player })
+            .build();
+    }
+
+    let mut log = GameLog::new();
+    log.log(LogCategory::General, "Welcome to the Wild Wild West");
+    log.log(LogCategory::Items, "You pick up the Health Potion.");
+    for _ in 0..2 {
+        LogBuilder::new(LogCategory::Combat)
+            .colored("Orc", RGB::named(rltk::RED))
+            .text(" hits ")
+            .colored("Player", RGB::named(rltk::YELLOW))
+            .text(", for ")
+            .damage(3)
+            .text(".")
+            .log(&mut log);
+    }
+    ecs.insert(log);
+    ecs.insert(Keymap::default());
+    ecs.insert(Options::default());
+    ecs.insert(Tileset::default());
+    ecs
+}
+
+/// The first entity called `name`, for tests that need to get hold of one of the
+/// fixture's entities.
+pub fn named(ecs: &World, name: &str) -> Entity {
+    let names = ecs.read_storage::<Name>();
+    let found = (&ecs.entities(), &names).join()
+        .find(|(_, entity_name)| entity_name.name == name)
+        .map(|(entity, _)| entity);
+    found.unwrap_or_else(|| panic!("nothing called {}", name))
+}
+
+pub fn player_hp(ecs: &World) -> i32 {
+    ecs.read_storage::<CombatStats>().get(*ecs.fetch::<Entity>()).unwrap().hp
+}
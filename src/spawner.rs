@@ -3,14 +3,29 @@ use specs::prelude::*;
 use crate::components::{
     CombatStats, Player, Renderable, Name,
     Position, Viewshed, Monster, BlocksTile,
-    Item, Potion
+    Item, Potion, HealthRegen, BlocksVisibility,
+    Door, Locked, Key, Hidden, Trap, TrapKind,
+    LightSource, Fuel, InBackpack, Wanderer
 };
-use crate::game_map::MAPWIDTH;
+use crate::game_map::{GameMap, TileType, MAPWIDTH};
+use crate::door_system;
+use crate::map_builders::{TownBuilding, BuildingKind, MINE_DEPTH};
 use crate::rect::Rect;
+use crate::clock::{GameClock, TimeOfDay};
 
 
 const MAX_MONSTERS: i32 = 2;
 const MAX_ITEMS: i32 = 2;
+const MAX_TRAPS: i32 = 1;
+/// How many wanderers can be out on the street at once.
+const MAX_WANDERERS: usize = 6;
+/// Oil in the lantern the player sets out with.
+const STARTING_FUEL: i32 = 600;
+
+/// The lock on the bank's front door; its key is kept at the sheriff's office.
+const BANK_LOCK: u32 = 1;
+/// Locks nobody has the key to.
+const NO_KEY: u32 = 0;
 
 
 pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
@@ -20,16 +35,18 @@ pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
             glyph: rltk::to_cp437('@'),
             fg: RGB::named(rltk::YELLOW),
             bg: RGB::named(rltk::BLACK),
+            render_order: 0
         })
         .with(Player)
         .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })
         .with(Name { name: "Player".to_string() })
         .with(CombatStats { max_hp: 30, hp: 30, defense: 2, power: 5 })
+        .with(HealthRegen { turns_per_hp: 6, turns_left: 6 })
         .build()
 }
 
 
-pub fn random_monster(ecs: &mut World, x: i32, y: i32) {
+pub fn random_monster(ecs: &mut World, x: i32, y: i32) -> Entity {
     let roll: i32;
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
@@ -42,84 +59,466 @@ pub fn random_monster(ecs: &mut World, x: i32, y: i32) {
 }
 
 
+/// Who's out on the street at each time of day.
+fn street_table(time: TimeOfDay) -> &'static [&'static str] {
+    match time {
+        TimeOfDay::Day => &["Drifter", "Cowhand"],
+        TimeOfDay::Dusk => &["Drifter", "Coyote", "Outlaw"],
+        TimeOfDay::Night => &["Coyote", "Coyote", "Outlaw"]
+    }
+}
+
+/// Puts someone on one of the open `ground` tiles, picked from whoever is about at
+/// this time of day.
+pub fn spawn_wanderer(ecs: &mut World, ground: &[usize]) {
+    let table = street_table(ecs.fetch::<GameClock>().time_of_day());
+    let (spot, name) = {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        match (rng.random_slice_entry(ground), rng.random_slice_entry(table)) {
+            (Some(spot), Some(name)) => (*spot, *name),
+            _ => return
+        }
+    };
+    if let Some(newcomer) = spawn_entity(ecs, spot as i32 % MAPWIDTH, spot as i32 / MAPWIDTH, name) {
+        ecs.write_storage::<Wanderer>().insert(newcomer, Wanderer).expect("Unable to insert wanderer");
+    }
+}
+
+/// True once there are as many wanderers about as the street can take.
+pub fn street_is_full(ecs: &World) -> bool {
+    ecs.read_storage::<Wanderer>().join().count() >= MAX_WANDERERS
+}
+
+
 pub fn spawn_room(ecs: &mut World, room: &Rect) {
-    let mut monster_spawn_points = Vec::<i32>::new();
-    let mut item_spawn_points = Vec::<i32>::new();
+    let mut possible_targets = Vec::<usize>::new();
+    for y in room.y1 + 1 ..= room.y2 {
+        for x in room.x1 + 1 ..= room.x2 {
+            possible_targets.push(((y * MAPWIDTH) + x) as usize);
+        }
+    }
+
+    spawn_region(ecs, &possible_targets);
+}
+
+
+pub fn spawn_region(ecs: &mut World, area: &[usize]) {
+    let mut areas = Vec::from(area);
+    let mut monster_spawn_points = Vec::<usize>::new();
+    let mut item_spawn_points = Vec::<usize>::new();
+    let mut trap_spawn_points = Vec::<usize>::new();
 
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        let num_monsters = rng.roll_dice(1, MAX_MONSTERS + 1) - 1;
-        let num_items = rng.roll_dice(1, MAX_ITEMS + 1) - 1;
-
+        let num_monsters = i32::min(areas.len() as i32, rng.roll_dice(1, MAX_MONSTERS + 1) - 1);
         for _ in 0..num_monsters {
-            let mut added = false;
-            while !added {
-                let x = room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1));
-                let y = room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1));
-                let idx = (y * MAPWIDTH) + x;
-                if !monster_spawn_points.contains(&idx) {
-                    monster_spawn_points.push(idx);
-                    added = true;
-                }
-            }
+            let array_index = rng.random_slice_index(&areas).unwrap();
+            monster_spawn_points.push(areas.remove(array_index));
         }
 
+        let num_items = i32::min(areas.len() as i32, rng.roll_dice(1, MAX_ITEMS + 1) - 1);
         for _ in 0..num_items {
-            let mut added = false;
-            while !added {
-                let x = room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1));
-                let y = room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1));
-                let idx = (y * MAPWIDTH) + x;
-                if !item_spawn_points.contains(&idx) {
-                    item_spawn_points.push(idx);
-                    added = true;
-                }
-            }
+            let array_index = rng.random_slice_index(&areas).unwrap();
+            item_spawn_points.push(areas.remove(array_index));
+        }
+
+        let num_traps = i32::min(areas.len() as i32, rng.roll_dice(1, MAX_TRAPS + 1) - 1);
+        for _ in 0..num_traps {
+            let array_index = rng.random_slice_index(&areas).unwrap();
+            trap_spawn_points.push(areas.remove(array_index));
         }
     }
 
     for idx in monster_spawn_points.iter() {
-        let x = *idx % MAPWIDTH;
-        let y = *idx / MAPWIDTH;
+        let x = *idx as i32 % MAPWIDTH;
+        let y = *idx as i32 / MAPWIDTH;
         random_monster(ecs, x, y);
     }
     for idx in item_spawn_points.iter() {
-        let x = *idx % MAPWIDTH;
-        let y = *idx / MAPWIDTH;
-        health_potion(ecs, x, y);
+        let x = *idx as i32 % MAPWIDTH;
+        let y = *idx as i32 / MAPWIDTH;
+        random_item(ecs, x, y);
+    }
+    for idx in trap_spawn_points.iter() {
+        let x = *idx as i32 % MAPWIDTH;
+        let y = *idx as i32 / MAPWIDTH;
+        random_trap(ecs, x, y);
+    }
+
+    spawn_torch(ecs, &areas);
+}
+
+
+/// Hangs a torch by a wall somewhere in the area. Cellars are kept lit; down in the
+/// mines only the odd torch is still burning.
+fn spawn_torch(ecs: &mut World, area: &[usize]) {
+    let by_a_wall: Vec<usize> = {
+        let map = ecs.fetch::<GameMap>();
+        area.iter().copied()
+            .filter(|idx| {
+                let (x, y) = (*idx as i32 % map.width, *idx as i32 / map.width);
+                [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                    let (wall_x, wall_y) = (x + dx, y + dy);
+                    wall_x >= 0 && wall_x < map.width && wall_y >= 0 && wall_y < map.height
+                        && map.tiles[map.xy_idx(wall_x, wall_y)] == TileType::Wall
+                })
+            })
+            .collect()
+    };
+    let spot = {
+        let depth = ecs.fetch::<GameMap>().depth;
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        if depth >= MINE_DEPTH && rng.roll_dice(1, 4) > 1 {
+            return;
+        }
+        match rng.random_slice_entry(&by_a_wall) {
+            Some(idx) => *idx,
+            None => return
+        }
+    };
+    torch(ecs, spot as i32 % MAPWIDTH, spot as i32 / MAPWIDTH);
+}
+
+
+/// Spawns an entity by name, as used by prefabs. Names it doesn't know spawn nothing.
+pub fn spawn_entity(ecs: &mut World, x: i32, y: i32, name: &str) -> Option<Entity> {
+    match name {
+        "Goblin" => Some(goblin(ecs, x, y)),
+        "Orc" => Some(orc(ecs, x, y)),
+        "Orc Warlord" => Some(orc_warlord(ecs, x, y)),
+        "Coyote" => Some(monster(ecs, x, y, rltk::to_cp437('c'), name)),
+        "Outlaw" => Some(outlaw(ecs, x, y)),
+        "Health Potion" => Some(health_potion(ecs, x, y)),
+        "Lantern" => Some(dropped_lantern(ecs, x, y)),
+        "Torch" => Some(torch(ecs, x, y)),
+        "Bear Trap" => Some(trap(ecs, x, y, TrapKind::BearTrap)),
+        "Tripwire Shotgun" => Some(trap(ecs, x, y, TrapKind::TripwireShotgun)),
+        "Snake Pit" => Some(trap(ecs, x, y, TrapKind::SnakePit)),
+        "Bartender" | "Drifter" | "Cowhand" => Some(townsfolk(ecs, x, y, name)),
+        "Bar Counter" => Some(prop(ecs, x, y, rltk::to_cp437('═'), RGB::named(rltk::SADDLEBROWN), name)),
+        "Card Table" => Some(prop(ecs, x, y, rltk::to_cp437('π'), RGB::named(rltk::PERU), name)),
+        _ => None
     }
 }
 
 
-fn orc(ecs: &mut World, x: i32, y: i32) { monster(ecs, x, y, rltk::to_cp437('o'), "Orc"); }
-fn goblin(ecs: &mut World, x: i32, y: i32) { monster(ecs, x, y, rltk::to_cp437('g'), "Goblin"); }
+/// Removes whatever was spawned inside a room, except the player.
+pub fn clear_region(ecs: &mut World, room: &Rect) {
+    let mut doomed = Vec::<Entity>::new();
+    {
+        let player_entity = ecs.fetch::<Entity>();
+        let entities = ecs.entities();
+        let positions = ecs.read_storage::<Position>();
 
-fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: u8, name: S) {
+        for (entity, pos) in (&entities, &positions).join() {
+            let inside = pos.x > room.x1 && pos.x <= room.x2 && pos.y > room.y1 && pos.y <= room.y2;
+            if inside && entity != *player_entity {
+                doomed.push(entity);
+            }
+        }
+    }
+
+    for entity in doomed {
+        ecs.delete_entity(entity).expect("Unable to clear entity from region");
+    }
+}
+
+
+fn orc(ecs: &mut World, x: i32, y: i32) -> Entity { monster(ecs, x, y, rltk::to_cp437('o'), "Orc") }
+fn goblin(ecs: &mut World, x: i32, y: i32) -> Entity { monster(ecs, x, y, rltk::to_cp437('g'), "Goblin") }
+
+fn orc_warlord(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('O'),
+            fg: RGB::named(rltk::ORANGE_RED),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1
+        })
+        .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })
+        .with(Monster)
+        .with(Name { name: "Orc Warlord".to_string() })
+        .with(BlocksTile)
+        .with(CombatStats { max_hp: 40, hp: 40, defense: 3, power: 8 })
+        .build()
+}
+
+fn outlaw(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('@'),
+            fg: RGB::named(rltk::RED),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1
+        })
+        .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })
+        .with(Monster)
+        .with(Name { name: "Outlaw".to_string() })
+        .with(BlocksTile)
+        .with(CombatStats { max_hp: 24, hp: 24, defense: 2, power: 6 })
+        .build()
+}
+
+fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: u8, name: S) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
             glyph,
             fg: RGB::named(rltk::RED),
             bg: RGB::named(rltk::BLACK),
+            render_order: 1
         })
         .with(Viewshed { visible_tiles: Vec::new(), range: 8, dirty: true })
         .with(Monster)
         .with(Name { name: name.to_string() })
         .with(BlocksTile)
         .with(CombatStats { max_hp: 16, hp: 16, defense: 1, power: 4 })
-        .build();
+        .build()
+}
+
+fn random_item(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let roll = ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, 6);
+    match roll {
+        1 => dropped_lantern(ecs, x, y),
+        _ => health_potion(ecs, x, y)
+    }
 }
 
-fn health_potion(ecs: &mut World, x: i32, y: i32) {
+fn health_potion(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position{ x, y })
         .with(Renderable {
             glyph: rltk::to_cp437(';'),
             fg: RGB::named(rltk::MAGENTA),
-            bg: RGB::named(rltk::BLACK)
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2
         })
         .with(Name { name: "Health Potion".to_string() })
         .with(Item)
         .with(Potion { heal_amount: 8 })
+        .build()
+}
+
+
+fn lantern_renderable() -> Renderable {
+    Renderable {
+        glyph: rltk::to_cp437('δ'),
+        fg: RGB::named(rltk::GOLD),
+        bg: RGB::named(rltk::BLACK),
+        render_order: 2
+    }
+}
+
+/// Puts a lantern with `fuel` turns of oil in someone's backpack.
+pub fn lantern(ecs: &mut World, owner: Entity, fuel: i32) {
+    ecs.create_entity()
+        .with(lantern_renderable())
+        .with(Name { name: "Lantern".to_string() })
+        .with(Item)
+        .with(LightSource { range: 5, intensity: 0.9 })
+        .with(Fuel { turns: fuel })
+        .with(InBackpack { owner })
         .build();
 }
+
+pub fn starting_lantern(ecs: &mut World, owner: Entity) {
+    lantern(ecs, owner, STARTING_FUEL);
+}
+
+/// Somebody's lantern, left behind with a little oil still in it.
+fn dropped_lantern(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let fuel = ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, 200) + 100;
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(lantern_renderable())
+        .with(Name { name: "Lantern".to_string() })
+        .with(Item)
+        .with(LightSource { range: 5, intensity: 0.9 })
+        .with(Fuel { turns: fuel })
+        .build()
+}
+
+/// A torch in a bracket on the wall next to its tile; it never burns out.
+fn torch(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('¡'),
+            fg: RGB::named(rltk::ORANGE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 3
+        })
+        .with(Name { name: "Torch".to_string() })
+        .with(LightSource { range: 6, intensity: 1.0 })
+        .build()
+}
+
+
+pub fn random_trap(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let roll = ecs.write_resource::<RandomNumberGenerator>().roll_dice(1, 3);
+    match roll {
+        1 => trap(ecs, x, y, TrapKind::BearTrap),
+        2 => trap(ecs, x, y, TrapKind::TripwireShotgun),
+        _ => trap(ecs, x, y, TrapKind::SnakePit)
+    }
+}
+
+/// Traps start out hidden; see `VisibilitySystem` and the search command.
+fn trap(ecs: &mut World, x: i32, y: i32, kind: TrapKind) -> Entity {
+    let (name, fg) = match kind {
+        TrapKind::BearTrap => ("Bear Trap", RGB::named(rltk::GREY)),
+        TrapKind::TripwireShotgun => ("Tripwire Shotgun", RGB::named(rltk::ORANGE)),
+        TrapKind::SnakePit => ("Snake Pit", RGB::named(rltk::GREEN))
+    };
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('^'),
+            fg,
+            bg: RGB::named(rltk::BLACK),
+            render_order: 3
+        })
+        .with(Name { name: name.to_string() })
+        .with(Trap { kind })
+        .with(Hidden)
+        .build()
+}
+
+
+/// Furnishes a town building and puts the right folk behind the counter.
+pub fn spawn_building(ecs: &mut World, building: &TownBuilding) {
+    let room = building.rect;
+    let door_y = building.door as i32 / MAPWIDTH;
+    let (center_x, _) = room.center();
+
+    // the back of the room is the row furthest from the door; `inward` steps back towards it
+    let (back_y, inward) = if door_y <= room.y1 { (room.y2, 1) } else { (room.y1 + 1, -1) };
+
+    match building.kind {
+        BuildingKind::Saloon => {
+            for x in room.x1 + 2 ..= room.x2 {
+                prop(ecs, x, back_y - inward, rltk::to_cp437('═'), RGB::named(rltk::SADDLEBROWN), "Bar Counter");
+            }
+            townsfolk(ecs, center_x, back_y, "Bartender");
+            prop(ecs, room.x1 + 2, back_y - 2 * inward, rltk::to_cp437('π'), RGB::named(rltk::PERU), "Card Table");
+            prop(ecs, room.x2 - 1, back_y - 2 * inward, rltk::to_cp437('π'), RGB::named(rltk::PERU), "Card Table");
+        },
+        BuildingKind::SheriffsOffice => {
+            prop(ecs, center_x, back_y - inward, rltk::to_cp437('╤'), RGB::named(rltk::SADDLEBROWN), "Desk");
+            townsfolk(ecs, center_x, back_y, "Sheriff");
+            key(ecs, room.x1 + 1, back_y, "Bank Key", BANK_LOCK);
+        },
+        BuildingKind::GeneralStore => {
+            for x in room.x1 + 1 ..= room.x2 {
+                if x != center_x {
+                    prop(ecs, x, back_y, rltk::to_cp437('≡'), RGB::named(rltk::BURLYWOOD), "Shelf");
+                }
+            }
+            townsfolk(ecs, center_x, back_y, "Shopkeeper");
+            health_potion(ecs, room.x1 + 1, back_y - inward);
+        },
+        BuildingKind::Bank => {
+            prop(ecs, room.x2, back_y, rltk::to_cp437('■'), RGB::named(rltk::GREY), "Safe");
+            townsfolk(ecs, center_x, back_y, "Bank Teller");
+        },
+        BuildingKind::Jail => {
+            for x in room.x1 + 1 ..= room.x2 {
+                prop(ecs, x, back_y - inward, rltk::to_cp437('║'), RGB::named(rltk::GREY), "Iron Bars");
+            }
+            townsfolk(ecs, center_x, back_y, "Prisoner");
+            townsfolk(ecs, room.x1 + 1, back_y - 2 * inward, "Deputy");
+        },
+        BuildingKind::AbandonedHouse => {
+            spawn_room(ecs, &room);
+        }
+    }
+
+    let lock = match building.kind {
+        BuildingKind::Bank => Some(Locked { lock: BANK_LOCK, difficulty: 18 }),
+        BuildingKind::AbandonedHouse => Some(Locked { lock: NO_KEY, difficulty: 8 }),
+        _ => None
+    };
+    door(ecs, building.door as i32 % MAPWIDTH, door_y, lock);
+}
+
+
+/// A closed door, which blocks movement and sight until it is opened.
+pub fn door(ecs: &mut World, x: i32, y: i32, lock: Option<Locked>) -> Entity {
+    let mut builder = ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437(door_system::CLOSED_DOOR_GLYPH),
+            fg: RGB::named(rltk::SADDLEBROWN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 3
+        })
+        .with(Name { name: "Door".to_string() })
+        .with(Door { open: false })
+        .with(BlocksTile)
+        .with(BlocksVisibility);
+    if let Some(lock) = lock {
+        builder = builder.with(lock);
+    }
+    builder.build()
+}
+
+/// Hangs doors in some of a dungeon's doorways. Now and then one is locked, and its
+/// key is long lost.
+pub fn spawn_doors(ecs: &mut World, doorways: &[usize]) {
+    for idx in doorways {
+        let (has_door, locked) = {
+            let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+            (rng.roll_dice(1, 2) == 1, rng.roll_dice(1, 8) == 1)
+        };
+        if has_door {
+            let lock = if locked { Some(Locked { lock: NO_KEY, difficulty: 12 }) } else { None };
+            door(ecs, *idx as i32 % MAPWIDTH, *idx as i32 / MAPWIDTH, lock);
+        }
+    }
+}
+
+fn key<S: ToString>(ecs: &mut World, x: i32, y: i32, name: S, lock: u32) {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('¬'),
+            fg: RGB::named(rltk::GOLD),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2
+        })
+        .with(Name { name: name.to_string() })
+        .with(Item)
+        .with(Key { lock })
+        .build();
+}
+
+
+pub fn hitching_post(ecs: &mut World, x: i32, y: i32) {
+    prop(ecs, x, y, rltk::to_cp437('╥'), RGB::named(rltk::SADDLEBROWN), "Hitching Post");
+}
+
+
+fn townsfolk<S: ToString>(ecs: &mut World, x: i32, y: i32, name: S) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('@'),
+            fg: RGB::named(rltk::GREEN),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 1
+        })
+        .with(Name { name: name.to_string() })
+        .with(BlocksTile)
+        .build()
+}
+
+fn prop<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: u8, fg: RGB, name: S) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable { glyph, fg, bg: RGB::named(rltk::BLACK), render_order: 2 })
+        .with(Name { name: name.to_string() })
+        .with(BlocksTile)
+        .build()
+}
@@ -0,0 +1,86 @@
+use specs::prelude::*;
+use crate::game_log::{GameLog, LogCategory};
+use crate::components::{Immobilized, Poisoned, SufferDamage};
+use super::RunState;
+
+/// Wears lingering effects down by a turn at the end of every round, hurting the
+/// poisoned as it goes.
+pub struct StatusEffectSystem;
+
+impl<'a> System<'a> for StatusEffectSystem {
+    type SystemData = ( Entities<'a>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, GameLog>,
+                        WriteStorage<'a, Immobilized>,
+                        WriteStorage<'a, Poisoned>,
+                        WriteStorage<'a, SufferDamage> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, player_entity, run_state, mut log, mut immobilized, mut poisoned, mut inflict_damage) = data;
+
+        if *run_state != RunState::MonsterTurn {
+            return;
+        }
+
+        let mut freed = Vec::new();
+        for (entity, stuck) in (&entities, &mut immobilized).join() {
+            stuck.turns -= 1;
+            if stuck.turns <= 0 {
+                freed.push(entity);
+            }
+        }
+        for entity in freed {
+            immobilized.remove(entity);
+            if entity == *player_entity {
+                log.log(LogCategory::General, "You pull yourself free.");
+            }
+        }
+
+        let mut cured = Vec::new();
+        for (entity, poison) in (&entities, &mut poisoned).join() {
+            SufferDamage::new_damage(&mut inflict_damage, entity, 1);
+            poison.turns -= 1;
+            if poison.turns <= 0 {
+                cured.push(entity);
+            }
+        }
+        for entity in cured {
+            poisoned.remove(entity);
+            if entity == *player_entity {
+                log.log(LogCategory::General, "The venom wears off.");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::CombatStats;
+    use crate::damage_system::DamageSystem;
+    use crate::snapshot::fixture_world;
+
+    #[test]
+    fn effects_wear_off_once_a_round_and_venom_hurts_meanwhile() {
+        let mut ecs = fixture_world();
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<Immobilized>().insert(player, Immobilized { turns: 1 }).unwrap();
+        ecs.write_storage::<Poisoned>().insert(player, Poisoned { turns: 2 }).unwrap();
+
+        ecs.insert(RunState::PlayerTurn);
+        StatusEffectSystem.run_now(&ecs);
+        assert!(ecs.read_storage::<SufferDamage>().is_empty());
+
+        ecs.insert(RunState::MonsterTurn);
+        for _ in 0..3 {
+            StatusEffectSystem.run_now(&ecs);
+            DamageSystem.run_now(&ecs);
+            ecs.maintain();
+        }
+        assert_eq!(ecs.read_storage::<CombatStats>().get(player).unwrap().hp, 28);
+        assert!(!ecs.read_storage::<Immobilized>().contains(player));
+        assert!(!ecs.read_storage::<Poisoned>().contains(player));
+        assert_eq!(ecs.fetch::<GameLog>().entries[0].fragments[0].text, "The venom wears off.");
+    }
+}
@@ -0,0 +1,333 @@
+use rltk::{RGB, VirtualKeyCode, to_char};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use super::frame_buffer::{Cell, FrameBuffer};
+use super::frontend::Frontend;
+
+const FRAME_TIME: Duration = Duration::from_millis(33);
+const CTRL_C: u8 = 3;
+
+#[derive(PartialEq, Debug)]
+enum InputEvent {
+    Key(VirtualKeyCode, bool),
+    Mouse(i32, i32, bool),
+    Quit
+}
+
+/// Draws the game with ANSI escape codes on whatever terminal stdout is attached to,
+/// and reads keys and mouse reports from stdin.
+pub struct TerminalFrontend {
+    frame: FrameBuffer,
+    on_screen: Vec<Option<Cell>>,
+    input: Receiver<u8>,
+    pending: VecDeque<u8>,
+    saved_tty: Option<String>
+}
+
+impl TerminalFrontend {
+    pub fn new(width: i32, height: i32) -> TerminalFrontend {
+        let (sender, input) = channel();
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            let mut stdin = std::io::stdin();
+            while let Ok(n) = stdin.read(&mut buffer) {
+                if n == 0 || buffer[..n].iter().any(|byte| sender.send(*byte).is_err()) {
+                    break;
+                }
+            }
+        });
+
+        let saved_tty = stty(&["-g"]).map(|settings| settings.trim().to_string());
+        stty(&["raw", "-echo"]);
+        // alternate screen, hidden cursor, mouse motion reported in SGR format
+        print!("\x1b[?1049h\x1b[?25l\x1b[?1003h\x1b[?1006h\x1b[2J");
+
+        TerminalFrontend {
+            frame: FrameBuffer::new(width, height),
+            on_screen: vec![None; (width * height) as usize],
+            input,
+            pending: VecDeque::new(),
+            saved_tty
+        }
+    }
+
+    /// Collects this frame's input: mouse reports are all applied, but only one key is
+    /// taken per frame so that typing ahead isn't lost. Returns false on ctrl-c.
+    fn poll_input(&mut self) -> bool {
+        self.pending.extend(self.input.try_iter());
+        self.frame.key = None;
+        self.frame.shift = false;
+        self.frame.left_click = false;
+
+        while let Some((event, used)) = parse_input(self.pending.make_contiguous()) {
+            self.pending.drain(..used);
+            match event {
+                InputEvent::Quit => return false,
+                InputEvent::Mouse(x, y, clicked) => {
+                    self.frame.mouse_pos = (x, y);
+                    self.frame.left_click |= clicked;
+                },
+                InputEvent::Key(key, shift) => {
+                    self.frame.key = Some(key);
+                    self.frame.shift = shift;
+                    break;
+                }
+            }
+        }
+        true
+    }
+
+    /// Writes only the cells that changed since the last frame.
+    fn present(&mut self) {
+        let mut out = String::new();
+        let mut cursor = None;
+        let mut colors = None;
+        for y in 0..self.frame.height {
+            for x in 0..self.frame.width {
+                let idx = (y * self.frame.width + x) as usize;
+                let cell = self.frame.get(x, y);
+                if self.on_screen[idx] == Some(cell) {
+                    continue;
+                }
+                if cursor != Some((x, y)) {
+                    out.push_str(&format!("\x1b[{};{}H", y + 1, x + 1));
+                }
+                let (fg, bg) = (to_rgb8(cell.fg), to_rgb8(cell.bg));
+                if colors != Some((fg, bg)) {
+                    out.push_str(&format!("\x1b[38;2;{};{};{};48;2;{};{};{}m", fg.0, fg.1, fg.2, bg.0, bg.1, bg.2));
+                    colors = Some((fg, bg));
+                }
+                out.push(to_char(cell.glyph));
+                cursor = Some((x + 1, y));
+                self.on_screen[idx] = Some(cell);
+            }
+        }
+
+        if !out.is_empty() {
+            let stdout = std::io::stdout();
+            let mut lock = stdout.lock();
+            let _ = lock.write_all(out.as_bytes());
+            let _ = lock.flush();
+        }
+    }
+}
+
+impl Drop for TerminalFrontend {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?1006l\x1b[?1003l\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+        match &self.saved_tty {
+            Some(settings) => { stty(&[settings]); },
+            None => { stty(&["sane"]); }
+        }
+    }
+}
+
+impl Frontend for TerminalFrontend {
+    fn cls(&mut self) {
+        self.frame.cls();
+    }
+
+    fn set(&mut self, x: i32, y: i32, fg: RGB, bg: RGB, glyph: u8) {
+        self.frame.set(x, y, fg, bg, glyph);
+    }
+
+    fn key(&self) -> Option<VirtualKeyCode> {
+        self.frame.key
+    }
+
+    fn shift(&self) -> bool {
+        self.frame.shift
+    }
+
+    fn mouse_pos(&self) -> (i32, i32) {
+        self.frame.mouse_pos
+    }
+
+    fn left_click(&self) -> bool {
+        self.frame.left_click
+    }
+
+    fn frame_time_ms(&self) -> f32 {
+        self.frame.frame_time_ms
+    }
+}
+
+/// Runs `tick` about thirty times a second against the terminal until ctrl-c.
+pub fn main_loop<F: FnMut(&mut TerminalFrontend)>(width: i32, height: i32, mut tick: F) {
+    let mut frontend = TerminalFrontend::new(width, height);
+    let mut last_frame = Instant::now();
+    while frontend.poll_input() {
+        let started = Instant::now();
+        frontend.frame.frame_time_ms = (started - last_frame).as_secs_f32() * 1000.0;
+        last_frame = started;
+
+        tick(&mut frontend);
+        frontend.present();
+
+        if let Some(rest) = FRAME_TIME.checked_sub(started.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    String::from_utf8(output.stdout).ok()
+}
+
+fn to_rgb8(color: RGB) -> (u8, u8, u8) {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0) as u8;
+    (channel(color.r), channel(color.g), channel(color.b))
+}
+
+/// Parses one event off the front of the input, returning it and how many bytes it used.
+/// Returns None when the input is empty or ends partway through an escape sequence.
+fn parse_input(input: &[u8]) -> Option<(InputEvent, usize)> {
+    let first = *input.first()?;
+    if first != 0x1b {
+        return Some((byte_to_key(first), 1));
+    }
+
+    match input.get(1) {
+        None => Some((InputEvent::Key(VirtualKeyCode::Escape, false), 1)),
+        Some(b'[') => parse_csi(input),
+        Some(b'O') => {
+            let key = match *input.get(2)? {
+                b'A' => VirtualKeyCode::Up,
+                b'B' => VirtualKeyCode::Down,
+                b'C' => VirtualKeyCode::Right,
+                b'D' => VirtualKeyCode::Left,
+                b'H' => VirtualKeyCode::Home,
+                b'F' => VirtualKeyCode::End,
+                b'P' => VirtualKeyCode::F1,
+                b'Q' => VirtualKeyCode::F2,
+                b'R' => VirtualKeyCode::F3,
+                b'S' => VirtualKeyCode::F4,
+                _ => VirtualKeyCode::Escape
+            };
+            Some((InputEvent::Key(key, false), 3))
+        },
+        Some(_) => Some((InputEvent::Key(VirtualKeyCode::Escape, false), 1))
+    }
+}
+
+/// Control sequences: cursor keys, `ESC [ n ~` editing keys and `ESC [ < b ; x ; y M` mouse reports.
+fn parse_csi(input: &[u8]) -> Option<(InputEvent, usize)> {
+    let end = 2 + input[2..].iter().position(|byte| (0x40..=0x7e).contains(byte))?;
+    let params = std::str::from_utf8(&input[2..end]).unwrap_or("");
+    let used = end + 1;
+
+    if let Some(mouse) = params.strip_prefix('<') {
+        let numbers: Vec<i32> = mouse.split(';').filter_map(|n| n.parse().ok()).collect();
+        if numbers.len() == 3 {
+            let button = numbers[0];
+            let clicked = input[end] == b'M' && button & 32 == 0 && button & 3 == 0;
+            return Some((InputEvent::Mouse(numbers[1] - 1, numbers[2] - 1, clicked), used));
+        }
+    }
+
+    // a second parameter carries the modifiers, e.g. `ESC [ 1 ; 2 A` for shift-up
+    let (number, modifiers) = params.split_once(';').unwrap_or((params, ""));
+    let shift = modifiers.parse::<u8>().is_ok_and(|modifiers| modifiers.saturating_sub(1) & 1 == 1);
+
+    let key = match (input[end], number) {
+        (b'A', _) => VirtualKeyCode::Up,
+        (b'B', _) => VirtualKeyCode::Down,
+        (b'C', _) => VirtualKeyCode::Right,
+        (b'D', _) => VirtualKeyCode::Left,
+        (b'H', _) | (b'~', "1") | (b'~', "7") => VirtualKeyCode::Home,
+        (b'F', _) | (b'~', "4") | (b'~', "8") => VirtualKeyCode::End,
+        (b'~', "2") => VirtualKeyCode::Insert,
+        (b'~', "3") => VirtualKeyCode::Delete,
+        (b'~', "5") => VirtualKeyCode::PageUp,
+        (b'~', "6") => VirtualKeyCode::PageDown,
+        (b'~', "11") => VirtualKeyCode::F1,
+        (b'~', "12") => VirtualKeyCode::F2,
+        (b'~', "13") => VirtualKeyCode::F3,
+        (b'~', "14") => VirtualKeyCode::F4,
+        (b'~', "15") => VirtualKeyCode::F5,
+        _ => return Some((InputEvent::Key(VirtualKeyCode::Escape, false), used))
+    };
+    Some((InputEvent::Key(key, shift), used))
+}
+
+fn byte_to_key(byte: u8) -> InputEvent {
+    use VirtualKeyCode::*;
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z
+    ];
+    const DIGITS: [VirtualKeyCode; 10] = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+
+    let (key, shift) = match byte {
+        CTRL_C => return InputEvent::Quit,
+        b'a'..=b'z' => (LETTERS[(byte - b'a') as usize], false),
+        b'A'..=b'Z' => (LETTERS[(byte - b'A') as usize], true),
+        b'0'..=b'9' => (DIGITS[(byte - b'0') as usize], false),
+        b'\r' | b'\n' => (Return, false),
+        b'\t' => (Tab, false),
+        b' ' => (Space, false),
+        0x7f | 0x08 => (Back, false),
+        b'.' => (Period, false),
+        b'>' => (Period, true),
+        b',' => (Comma, false),
+        b'<' => (Comma, true),
+        b'/' => (Slash, false),
+        b'?' => (Slash, true),
+        b'-' => (Minus, false),
+        b'=' => (Equals, false),
+        b';' => (Semicolon, false),
+        b':' => (Semicolon, true),
+        _ => (Unlabeled, false)
+    };
+    InputEvent::Key(key, shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_letters_digits_and_shifted_symbols() {
+        assert_eq!(parse_input(b"g"), Some((InputEvent::Key(VirtualKeyCode::G, false), 1)));
+        assert_eq!(parse_input(b"G"), Some((InputEvent::Key(VirtualKeyCode::G, true), 1)));
+        assert_eq!(parse_input(b"7"), Some((InputEvent::Key(VirtualKeyCode::Key7, false), 1)));
+        assert_eq!(parse_input(b">"), Some((InputEvent::Key(VirtualKeyCode::Period, true), 1)));
+        assert_eq!(parse_input(&[CTRL_C]), Some((InputEvent::Quit, 1)));
+    }
+
+    #[test]
+    fn parses_shifted_cursor_and_editing_keys() {
+        assert_eq!(parse_input(b"\x1b[1;2A"), Some((InputEvent::Key(VirtualKeyCode::Up, true), 6)));
+        assert_eq!(parse_input(b"\x1b[1;2D"), Some((InputEvent::Key(VirtualKeyCode::Left, true), 6)));
+        assert_eq!(parse_input(b"\x1b[1;6H"), Some((InputEvent::Key(VirtualKeyCode::Home, true), 6)), "ctrl-shift");
+        assert_eq!(parse_input(b"\x1b[1;8F"), Some((InputEvent::Key(VirtualKeyCode::End, true), 6)), "ctrl-alt-shift");
+        assert_eq!(parse_input(b"\x1b[5;4~"), Some((InputEvent::Key(VirtualKeyCode::PageUp, true), 6)), "alt-shift");
+        assert_eq!(parse_input(b"\x1b[1;5B"), Some((InputEvent::Key(VirtualKeyCode::Down, false), 6)), "ctrl only");
+        assert_eq!(parse_input(b"\x1b[1;3C"), Some((InputEvent::Key(VirtualKeyCode::Right, false), 6)), "alt only");
+    }
+
+    #[test]
+    fn parses_escape_sequences() {
+        assert_eq!(parse_input(b"\x1b[A"), Some((InputEvent::Key(VirtualKeyCode::Up, false), 3)));
+        assert_eq!(parse_input(b"\x1bOD"), Some((InputEvent::Key(VirtualKeyCode::Left, false), 3)));
+        assert_eq!(parse_input(b"\x1b[5~k"), Some((InputEvent::Key(VirtualKeyCode::PageUp, false), 4)));
+        assert_eq!(parse_input(b"\x1bOP"), Some((InputEvent::Key(VirtualKeyCode::F1, false), 3)));
+        assert_eq!(parse_input(b"\x1b"), Some((InputEvent::Key(VirtualKeyCode::Escape, false), 1)));
+        assert_eq!(parse_input(b"\x1b[1"), None);
+    }
+
+    #[test]
+    fn parses_sgr_mouse_reports() {
+        assert_eq!(parse_input(b"\x1b[<0;11;6M"), Some((InputEvent::Mouse(10, 5, true), 10)));
+        assert_eq!(parse_input(b"\x1b[<35;3;4M"), Some((InputEvent::Mouse(2, 3, false), 10)));
+        assert_eq!(parse_input(b"\x1b[<0;11;6m"), Some((InputEvent::Mouse(10, 5, false), 10)));
+    }
+}
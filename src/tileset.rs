@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use crate::game_map::TileType;
+
+/// Says which sprite in the tileset image each tile and each kind of entity uses.
+pub const TILESET_MAPPING_FILE: &str = "resources/tiles.cfg";
+
+/// Where tileset images are looked for.
+pub const RESOURCE_PATH: &str = "resources";
+
+const TILE_NAMES: [(&str, TileType); 6] = [
+    ("wall", TileType::Wall),
+    ("floor", TileType::Floor),
+    ("down_stairs", TileType::DownStairs),
+    ("sand", TileType::Sand),
+    ("road", TileType::Road),
+    ("wood_floor", TileType::WoodFloor)
+];
+
+/// Sprites are numbered left to right, top to bottom, sixteen to a row. Anything
+/// without a sprite is drawn with its glyph. The default tileset has no sprites at
+/// all, which is what frontends without a tile layer are given.
+#[derive(Default)]
+pub struct Tileset {
+    pub image: String,
+    tiles: HashMap<TileType, u8>,
+    entities: HashMap<String, u8>
+}
+
+/// How an entity's name is written in the mapping file: "Health Potion" is `health_potion`.
+fn entity_key(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "_")
+}
+
+impl Tileset {
+    pub fn tile_sprite(&self, tile: TileType) -> Option<u8> {
+        self.tiles.get(&tile).copied()
+    }
+
+    pub fn entity_sprite(&self, name: &str) -> Option<u8> {
+        self.entities.get(&entity_key(name)).copied()
+    }
+
+    /// Reads `image = file`, `tile.<tile> = sprite` and `entity.<name> = sprite` lines.
+    pub fn parse(text: &str) -> Result<Tileset, Vec<String>> {
+        let mut tileset = Tileset::default();
+        let mut errors = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => {
+                    errors.push(format!("line {}: expected `name = value`", number + 1));
+                    continue;
+                }
+            };
+            if name == "image" {
+                tileset.image = value.to_string();
+                continue;
+            }
+
+            let sprite = match value.parse::<u8>() {
+                Ok(sprite) => sprite,
+                Err(_) => {
+                    errors.push(format!("line {}: `{}` isn't a sprite number from 0 to 255", number + 1, value));
+                    continue;
+                }
+            };
+            if let Some(tile_name) = name.strip_prefix("tile.") {
+                match TILE_NAMES.iter().find(|(known, _)| *known == tile_name) {
+                    Some((_, tile)) => { tileset.tiles.insert(*tile, sprite); },
+                    None => errors.push(format!("line {}: unknown tile `{}`", number + 1, tile_name))
+                }
+            } else if let Some(entity_name) = name.strip_prefix("entity.") {
+                tileset.entities.insert(entity_key(entity_name), sprite);
+            } else {
+                errors.push(format!("line {}: expected `image`, `tile.<name>` or `entity.<name>`, not `{}`", number + 1, name));
+            }
+        }
+
+        if tileset.image.is_empty() {
+            errors.push("no tileset `image` given".to_string());
+        }
+        if errors.is_empty() { Ok(tileset) } else { Err(errors) }
+    }
+
+    /// Loads the mapping file and checks that the image it names is there.
+    pub fn load() -> Result<Tileset, Vec<String>> {
+        let text = std::fs::read_to_string(TILESET_MAPPING_FILE)
+            .map_err(|error| vec![format!("unable to read {}: {}", TILESET_MAPPING_FILE, error)])?;
+        let tileset = Tileset::parse(&text)?;
+        let image = format!("{}/{}", RESOURCE_PATH, tileset.image);
+        if !std::path::Path::new(&image).is_file() {
+            return Err(vec![format!("tileset image {} is missing", image)]);
+        }
+        Ok(tileset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_shipped_mapping_parses() {
+        let tileset = Tileset::parse(&std::fs::read_to_string(TILESET_MAPPING_FILE).unwrap()).unwrap();
+        assert_eq!(tileset.image, "example_tiles.jpg");
+        assert_eq!(tileset.tile_sprite(TileType::Wall), Some(0));
+        assert_eq!(tileset.tile_sprite(TileType::DownStairs), None);
+        assert_eq!(tileset.entity_sprite("Player"), Some(2));
+        assert_eq!(tileset.entity_sprite("Health Potion"), None);
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let errors = Tileset::parse("tile.lava = 3\nentity.orc = 300\nmonster.orc = 4\n").err().expect("should fail");
+        assert_eq!(errors, vec![
+            "line 1: unknown tile `lava`",
+            "line 2: `300` isn't a sprite number from 0 to 255",
+            "line 3: expected `image`, `tile.<name>` or `entity.<name>`, not `monster.orc`",
+            "no tileset `image` given"
+        ]);
+    }
+}
@@ -0,0 +1,179 @@
+use specs::prelude::*;
+use rltk::RandomNumberGenerator;
+use crate::game_map::GameMap;
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::options::Options;
+use crate::components::{
+    EntityMoved, Position, Trap, TrapKind, Hidden, Name, Renderable,
+    SufferDamage, Immobilized, Poisoned
+};
+
+/// Turns a bear trap holds its victim.
+const BEAR_TRAP_TURNS: i32 = 3;
+/// Turns snake venom keeps hurting.
+const VENOM_TURNS: i32 = 5;
+
+/// Springs the traps under anything that moved this turn, monsters included. A trap
+/// goes off whether or not it has been spotted, and is revealed if the player sees it.
+/// Bear traps and snake pits stay armed; a tripwire shotgun fires once.
+pub struct TrapSystem;
+
+impl<'a> System<'a> for TrapSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = ( Entities<'a>,
+                        ReadExpect<'a, Entity>,
+                        ReadExpect<'a, GameMap>,
+                        ReadExpect<'a, Options>,
+                        WriteExpect<'a, GameLog>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        WriteStorage<'a, EntityMoved>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Trap>,
+                        WriteStorage<'a, Hidden>,
+                        ReadStorage<'a, Name>,
+                        ReadStorage<'a, Renderable>,
+                        WriteStorage<'a, SufferDamage>,
+                        WriteStorage<'a, Immobilized>,
+                        WriteStorage<'a, Poisoned> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            entities,
+            player_entity,
+            map,
+            options,
+            mut log,
+            mut rng,
+            mut moved,
+            positions,
+            traps,
+            mut hidden,
+            names,
+            renderables,
+            mut inflict_damage,
+            mut immobilized,
+            mut poisoned
+        ) = data;
+
+        for (victim, _moved, pos) in (&entities, &moved, &positions).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            for trap_entity in map.tile_content[idx].iter() {
+                let trap = match traps.get(*trap_entity) {
+                    Some(trap) => trap,
+                    None => continue
+                };
+                let is_player = victim == *player_entity;
+                let seen = is_player || map.visible_tiles[idx];
+                if seen {
+                    hidden.remove(*trap_entity);
+                }
+
+                let mut damage = match trap.kind {
+                    TrapKind::BearTrap => rng.roll_dice(1, 4),
+                    TrapKind::TripwireShotgun => rng.roll_dice(3, 4),
+                    TrapKind::SnakePit => rng.roll_dice(1, 2)
+                };
+                if is_player {
+                    damage = options.difficulty.damage_taken(damage);
+                }
+                SufferDamage::new_damage(&mut inflict_damage, victim, damage);
+                match trap.kind {
+                    TrapKind::BearTrap => {
+                        immobilized.insert(victim, Immobilized { turns: BEAR_TRAP_TURNS }).expect("Unable to immobilize");
+                    },
+                    TrapKind::SnakePit => {
+                        poisoned.insert(victim, Poisoned { turns: VENOM_TURNS }).expect("Unable to poison");
+                    },
+                    TrapKind::TripwireShotgun => {
+                        entities.delete(*trap_entity).expect("Unable to delete spent trap");
+                    }
+                }
+
+                if seen {
+                    let victim_name = names.get(victim).map_or("Something", |name| name.name.as_str());
+                    let trap_name = names.get(*trap_entity).map_or("trap", |name| name.name.as_str());
+                    LogBuilder::new(LogCategory::Combat)
+                        .name(victim_name, renderables.get(victim))
+                        .text(" sets off a ")
+                        .name(trap_name, renderables.get(*trap_entity))
+                        .text(", for ")
+                        .damage(damage)
+                        .text(".")
+                        .log(&mut log);
+                }
+            }
+        }
+
+        moved.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spawner, try_move_player, search};
+    use crate::components::CombatStats;
+    use crate::map_ai_system::MapIndexingSystem;
+    use crate::damage_system::DamageSystem;
+    use crate::snapshot::{fixture_world, named, player_hp};
+
+    fn spring_traps(ecs: &mut World) {
+        MapIndexingSystem.run_now(ecs);
+        TrapSystem.run_now(ecs);
+        DamageSystem.run_now(ecs);
+        ecs.maintain();
+    }
+
+    #[test]
+    fn a_hidden_bear_trap_bites_reveals_itself_and_holds_on() {
+        let mut ecs = fixture_world();
+        spawner::spawn_entity(&mut ecs, 11, 11, "Bear Trap");
+        let trap = {
+            let traps = ecs.read_storage::<Trap>();
+            (&ecs.entities(), &traps).join().next().unwrap().0
+        };
+        assert!(ecs.read_storage::<Hidden>().contains(trap));
+        MapIndexingSystem.run_now(&ecs);
+
+        try_move_player(&mut ecs, 1, 1);
+        spring_traps(&mut ecs);
+        let player = *ecs.fetch::<Entity>();
+        assert!(player_hp(&ecs) < 30);
+        assert!(!ecs.read_storage::<Hidden>().contains(trap));
+        assert!(ecs.read_storage::<Immobilized>().contains(player));
+        assert!(ecs.read_storage::<EntityMoved>().is_empty());
+
+        try_move_player(&mut ecs, 1, 0);
+        assert_eq!(*ecs.fetch::<rltk::Point>(), rltk::Point::new(11, 11));
+    }
+
+    #[test]
+    fn monsters_set_off_traps_and_a_shotgun_only_fires_once() {
+        let mut ecs = fixture_world();
+        spawner::spawn_entity(&mut ecs, 12, 10, "Tripwire Shotgun");
+        let orc = named(&ecs, "Orc");
+        ecs.write_storage::<EntityMoved>().insert(orc, EntityMoved).unwrap();
+        spring_traps(&mut ecs);
+
+        assert!(ecs.read_storage::<CombatStats>().get(orc).unwrap().hp < 16);
+        assert!(ecs.read_storage::<Trap>().is_empty());
+        let entry: String = ecs.fetch::<GameLog>().entries[0].fragments.iter().map(|fragment| fragment.text.as_str()).collect();
+        assert!(entry.starts_with("Orc sets off a Tripwire Shotgun, for "), "{}", entry);
+    }
+
+    #[test]
+    fn searching_turns_up_traps_close_by() {
+        let mut ecs = fixture_world();
+        spawner::spawn_entity(&mut ecs, 11, 11, "Snake Pit");
+        spawner::spawn_entity(&mut ecs, 15, 11, "Snake Pit");
+
+        for _ in 0..20 {
+            search(&mut ecs);
+        }
+        let hidden = ecs.read_storage::<Hidden>();
+        let positions = ecs.read_storage::<Position>();
+        let still_hidden: Vec<i32> = (&hidden, &positions).join().map(|(_, pos)| pos.x).collect();
+        assert_eq!(still_hidden, vec![15], "only the pit next to the player is found");
+        assert!(ecs.fetch::<GameLog>().entries.iter().any(|entry| entry.fragments[0].text == "You find a Snake Pit."));
+    }
+}
@@ -0,0 +1,422 @@
+use specs::prelude::*;
+use rltk::{Point, BaseMap, a_star_search};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use crate::game_map::GameMap;
+use crate::components::{Position, Monster, Item, Name, CombatStats, Trap, Hidden, Door};
+
+pub enum Destination {
+    /// Wherever the nearest unexplored tile is.
+    Unexplored,
+    Tile(Point),
+    /// Staying put until healed.
+    Rest,
+    /// The same step a number of times over. A step of (0, 0) waits.
+    Repeat { delta: (i32, i32), times: u32 },
+    /// Straight ahead until the way is blocked, the walls either side change or a door
+    /// comes up.
+    Run { delta: (i32, i32), sides: Option<Vec<bool>> }
+}
+
+/// Walking somewhere, or resting, over several turns. Remembers how healthy the player
+/// was and what they could see at the last step, so the walk can stop as soon as
+/// either changes. Every repeated action stops the same way.
+pub struct Travel {
+    destination: Destination,
+    hp: i32,
+    seen: Vec<Entity>
+}
+
+pub enum TravelStep {
+    Move(i32, i32),
+    Wait,
+    Arrived,
+    Interrupted(String)
+}
+
+/// Monsters, items and spotted traps on tiles the player can see.
+fn in_view(ecs: &World) -> Vec<(Entity, bool)> {
+    let map = ecs.fetch::<GameMap>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let monsters = ecs.read_storage::<Monster>();
+    let items = ecs.read_storage::<Item>();
+    let traps = ecs.read_storage::<Trap>();
+    let hidden = ecs.read_storage::<Hidden>();
+
+    (&entities, &positions, !&hidden).join()
+        .map(|(entity, pos, _not_hidden)| (entity, pos))
+        .filter(|(_, pos)| map.visible_tiles[map.xy_idx(pos.x, pos.y)])
+        .filter(|(entity, _)| monsters.contains(*entity) || items.contains(*entity) || traps.contains(*entity))
+        .map(|(entity, _)| (entity, monsters.contains(entity)))
+        .collect()
+}
+
+fn player_hp(ecs: &World) -> (i32, i32) {
+    let stats = ecs.read_storage::<CombatStats>();
+    stats.get(*ecs.fetch::<Entity>()).map_or((0, 0), |stats| (stats.hp, stats.max_hp))
+}
+
+fn name_of(ecs: &World, entity: Entity) -> String {
+    ecs.read_storage::<Name>().get(entity).map_or("something".to_string(), |name| name.name.clone())
+}
+
+impl Travel {
+    fn new(ecs: &World, destination: Destination) -> Travel {
+        let seen = in_view(ecs).into_iter().map(|(entity, _)| entity).collect();
+        Travel { destination, hp: player_hp(ecs).0, seen }
+    }
+
+    /// Refuses to start with a monster in view.
+    pub fn explore(ecs: &World) -> Result<Travel, String> {
+        if in_view(ecs).iter().any(|(_, monster)| *monster) {
+            return Err("You can't explore with enemies in view.".to_string());
+        }
+        Ok(Travel::new(ecs, Destination::Unexplored))
+    }
+
+    /// Refuses to start with a monster in view or nothing to heal.
+    pub fn rest(ecs: &World) -> Result<Travel, String> {
+        if in_view(ecs).iter().any(|(_, monster)| *monster) {
+            return Err("You can't rest with enemies in view.".to_string());
+        }
+        let (hp, max_hp) = player_hp(ecs);
+        if hp >= max_hp {
+            return Err("You are already at full health.".to_string());
+        }
+        Ok(Travel::new(ecs, Destination::Rest))
+    }
+
+    /// Takes the same step `times` times, or waits that many turns for a step of (0, 0).
+    pub fn repeat(ecs: &World, delta: (i32, i32), times: u32) -> Travel {
+        Travel::new(ecs, Destination::Repeat { delta, times })
+    }
+
+    pub fn run(ecs: &World, delta: (i32, i32)) -> Travel {
+        Travel::new(ecs, Destination::Run { delta, sides: None })
+    }
+
+    /// Only tiles the player has seen can be travelled to.
+    pub fn to(ecs: &World, target: Point) -> Result<Travel, String> {
+        let map = ecs.fetch::<GameMap>();
+        let in_bounds = target.x >= 0 && target.x < map.width && target.y >= 0 && target.y < map.height;
+        if !in_bounds || !map.revealed_tiles[map.xy_idx(target.x, target.y)] {
+            return Err("You don't know the way there.".to_string());
+        }
+        Ok(Travel::new(ecs, Destination::Tile(target)))
+    }
+
+    /// The step to take next, or why the walk is over.
+    pub fn next_step(&mut self, ecs: &World) -> TravelStep {
+        let verb = match self.destination {
+            Destination::Unexplored => "exploring",
+            Destination::Tile(_) => "travelling",
+            Destination::Rest | Destination::Repeat { delta: (0, 0), .. } => "resting",
+            Destination::Repeat { .. } => "moving",
+            Destination::Run { .. } => "running"
+        };
+        let (hp, max_hp) = player_hp(ecs);
+        if hp < self.hp {
+            return TravelStep::Interrupted(format!("You stop {}: you've been hurt.", verb));
+        }
+        self.hp = hp;
+
+        let new_sights: Vec<(Entity, bool)> = in_view(ecs).into_iter()
+            .filter(|(entity, _)| !self.seen.contains(entity))
+            .collect();
+        self.seen.extend(new_sights.iter().map(|(entity, _)| *entity));
+        if let Some((entity, _)) = new_sights.iter().find(|(_, monster)| *monster) {
+            return TravelStep::Interrupted(format!("You stop {}: {} comes into view.", verb, name_of(ecs, *entity)));
+        }
+        let staying_put = matches!(self.destination, Destination::Rest | Destination::Repeat { delta: (0, 0), .. });
+        if !staying_put {
+            if let Some((entity, _)) = new_sights.first() {
+                return TravelStep::Interrupted(format!("You stop {}: you spot a {}.", verb, name_of(ecs, *entity)));
+            }
+        }
+
+        let map = ecs.fetch::<GameMap>();
+        let player = *ecs.fetch::<Point>();
+        match &mut self.destination {
+            Destination::Unexplored => {
+                match step_towards_unexplored(&map, player) {
+                    Some((delta_x, delta_y)) => TravelStep::Move(delta_x, delta_y),
+                    None => TravelStep::Interrupted("There is nothing left to explore.".to_string())
+                }
+            },
+            Destination::Tile(target) => {
+                let target = *target;
+                if player == target {
+                    return TravelStep::Arrived;
+                }
+                match step_towards(&map, player, target) {
+                    Some((delta_x, delta_y)) => TravelStep::Move(delta_x, delta_y),
+                    None => TravelStep::Interrupted("You can't find a way there.".to_string())
+                }
+            },
+            Destination::Rest => {
+                if hp >= max_hp {
+                    TravelStep::Interrupted("You feel rested.".to_string())
+                } else {
+                    TravelStep::Wait
+                }
+            },
+            Destination::Repeat { delta, times } => {
+                if *times == 0 {
+                    return TravelStep::Arrived;
+                }
+                *times -= 1;
+                match *delta {
+                    (0, 0) => TravelStep::Wait,
+                    (delta_x, delta_y) if can_step(&map, player, (delta_x, delta_y)) => TravelStep::Move(delta_x, delta_y),
+                    _ => TravelStep::Arrived
+                }
+            },
+            Destination::Run { delta, sides } => {
+                let around = open_sides(&map, player, *delta);
+                let junction = sides.as_ref().is_some_and(|before| *before != around);
+                let at_a_door = sides.is_some() && door_nearby(ecs, player, *delta);
+                if junction || at_a_door || !can_step(&map, player, *delta) {
+                    return TravelStep::Arrived;
+                }
+                *sides = Some(around);
+                TravelStep::Move(delta.0, delta.1)
+            }
+        }
+    }
+}
+
+/// Whether the tile one step away is free to walk into.
+fn can_step(map: &GameMap, from: Point, (delta_x, delta_y): (i32, i32)) -> bool {
+    let (x, y) = (from.x + delta_x, from.y + delta_y);
+    x >= 0 && x < map.width && y >= 0 && y < map.height && !map.blocked[map.xy_idx(x, y)]
+}
+
+/// Which of the tiles around `from` can be seen through, leaving out the ones straight
+/// ahead and straight behind. A change in these means a junction or a room.
+fn open_sides(map: &GameMap, from: Point, delta: (i32, i32)) -> Vec<bool> {
+    (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+        .filter(|&step| step != (0, 0) && step != delta && step != (-delta.0, -delta.1))
+        .map(|(dx, dy)| {
+            let (x, y) = (from.x + dx, from.y + dy);
+            x >= 0 && x < map.width && y >= 0 && y < map.height && !map.is_opaque(map.xy_idx(x, y))
+        })
+        .collect()
+}
+
+/// Whether a door, open or shut, is next to `from` anywhere but straight behind.
+fn door_nearby(ecs: &World, from: Point, delta: (i32, i32)) -> bool {
+    let positions = ecs.read_storage::<Position>();
+    let doors = ecs.read_storage::<Door>();
+    (&positions, &doors).join().any(|(pos, _door)| {
+        let step = (pos.x - from.x, pos.y - from.y);
+        step.0.abs() <= 1 && step.1.abs() <= 1 && step != (0, 0) && step != (-delta.0, -delta.1)
+    })
+}
+
+/// The first step along the shortest path to `target`.
+fn step_towards(map: &GameMap, from: Point, target: Point) -> Option<(i32, i32)> {
+    let path = a_star_search(map.xy_idx(from.x, from.y), map.xy_idx(target.x, target.y), map);
+    if !path.success || path.steps.len() < 2 {
+        return None;
+    }
+    let step = path.steps[1] as i32;
+    Some((step % map.width - from.x, step / map.width - from.y))
+}
+
+/// Whether a tile the player knows they can stand on borders one they haven't seen.
+fn is_frontier(map: &GameMap, idx: usize) -> bool {
+    let x = idx as i32 % map.width;
+    let y = idx as i32 / map.width;
+    (-1..=1).flat_map(|dx| (-1..=1).map(move |dy| (x + dx, y + dy)))
+        .filter(|&(nx, ny)| nx >= 0 && nx < map.width && ny >= 0 && ny < map.height)
+        .any(|(nx, ny)| !map.revealed_tiles[map.xy_idx(nx, ny)])
+}
+
+/// The first step along the cheapest path through revealed tiles to the nearest
+/// unexplored edge of the map.
+pub fn step_towards_unexplored(map: &GameMap, from: Point) -> Option<(i32, i32)> {
+    let start = map.xy_idx(from.x, from.y);
+    let mut cost: HashMap<usize, u32> = HashMap::new();
+    let mut came_from: HashMap<usize, usize> = HashMap::new();
+    let mut open = BinaryHeap::new();
+    cost.insert(start, 0);
+    open.push(Reverse((0, start)));
+
+    while let Some(Reverse((so_far, idx))) = open.pop() {
+        if so_far > cost[&idx] {
+            continue;
+        }
+        if idx != start && is_frontier(map, idx) {
+            let mut step = idx;
+            while came_from[&step] != start {
+                step = came_from[&step];
+            }
+            let step_x = step as i32 % map.width;
+            let step_y = step as i32 / map.width;
+            return Some((step_x - from.x, step_y - from.y));
+        }
+        for (exit, exit_cost) in map.get_available_exits(idx) {
+            if !map.revealed_tiles[exit] {
+                continue;
+            }
+            let total = so_far + (exit_cost * 100.0) as u32;
+            if cost.get(&exit).is_none_or(|known| total < *known) {
+                cost.insert(exit, total);
+                came_from.insert(exit, idx);
+                open.push(Reverse((total, exit)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_map::TileType;
+    use crate::snapshot::{fixture_world, named};
+
+    fn hide_the_corridor_end(ecs: &World) {
+        let mut map = ecs.fetch_mut::<GameMap>();
+        for y in 4..=16 {
+            for x in 27..=31 {
+                let idx = map.xy_idx(x, y);
+                map.revealed_tiles[idx] = false;
+            }
+        }
+    }
+
+    #[test]
+    fn heads_for_the_nearest_unexplored_tile() {
+        let ecs = fixture_world();
+        assert_eq!(step_towards_unexplored(&ecs.fetch::<GameMap>(), Point::new(10, 10)), None);
+
+        hide_the_corridor_end(&ecs);
+        assert_eq!(step_towards_unexplored(&ecs.fetch::<GameMap>(), Point::new(10, 10)), Some((1, 0)));
+
+        // an unseen stretch of the west wall is closer
+        let mut map = ecs.fetch_mut::<GameMap>();
+        let idx = map.xy_idx(4, 10);
+        map.revealed_tiles[idx] = false;
+        assert_eq!(step_towards_unexplored(&map, Point::new(10, 10)), Some((-1, 0)));
+    }
+
+    #[test]
+    fn stops_for_monsters_items_and_damage() {
+        let mut ecs = fixture_world();
+        hide_the_corridor_end(&ecs);
+        assert!(matches!(Travel::explore(&ecs), Err(reason) if reason == "You can't explore with enemies in view."));
+
+        // with the orc out of sight, the potion already in view doesn't stop anything
+        let orc = named(&ecs, "Orc");
+        ecs.write_storage::<Position>().get_mut(orc).unwrap().x = 26;
+        let mut explore = Travel::explore(&ecs).unwrap();
+        assert!(matches!(explore.next_step(&ecs), TravelStep::Move(1, 0)));
+
+        crate::spawner::spawn_entity(&mut ecs, 16, 6, "Health Potion");
+        assert!(matches!(explore.next_step(&ecs), TravelStep::Interrupted(reason) if reason == "You stop exploring: you spot a Health Potion."));
+        assert!(matches!(explore.next_step(&ecs), TravelStep::Move(1, 0)));
+
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<CombatStats>().get_mut(player).unwrap().hp -= 1;
+        assert!(matches!(explore.next_step(&ecs), TravelStep::Interrupted(reason) if reason == "You stop exploring: you've been hurt."));
+
+        let mut explore = Travel::explore(&ecs).unwrap();
+        ecs.fetch_mut::<GameMap>().visible_tiles.iter_mut().for_each(|visible| *visible = true);
+        assert!(matches!(explore.next_step(&ecs), TravelStep::Interrupted(reason) if reason == "You stop exploring: Orc comes into view."));
+    }
+
+    #[test]
+    fn travels_to_a_revealed_tile_and_stops_there() {
+        let ecs = fixture_world();
+        assert!(Travel::to(&ecs, Point::new(40, 10)).is_err());
+
+        // the orc is already in view, so it doesn't stop the walk
+        let mut travel = Travel::to(&ecs, Point::new(10, 13)).unwrap();
+        assert!(matches!(travel.next_step(&ecs), TravelStep::Move(0, 1)));
+
+        *ecs.fetch_mut::<Point>() = Point::new(10, 13);
+        assert!(matches!(travel.next_step(&ecs), TravelStep::Arrived));
+
+        let mut walled_in = Travel::to(&ecs, Point::new(4, 4)).unwrap();
+        assert!(matches!(walled_in.next_step(&ecs), TravelStep::Interrupted(reason) if reason == "You can't find a way there."));
+    }
+
+    /// Follows a walk to its end, moving the player along, and says where it stopped.
+    fn walk(ecs: &World, travel: &mut Travel) -> (Point, usize) {
+        let mut steps = 0;
+        while let TravelStep::Move(delta_x, delta_y) = travel.next_step(ecs) {
+            let mut player = ecs.fetch_mut::<Point>();
+            *player = Point::new(player.x + delta_x, player.y + delta_y);
+            steps += 1;
+        }
+        (*ecs.fetch::<Point>(), steps)
+    }
+
+    #[test]
+    fn repeated_steps_stop_at_the_count_or_a_wall() {
+        let ecs = fixture_world();
+        *ecs.fetch_mut::<Point>() = Point::new(10, 7);
+        assert_eq!(walk(&ecs, &mut Travel::repeat(&ecs, (0, -1), 5)), (Point::new(10, 5), 2));
+        assert_eq!(walk(&ecs, &mut Travel::repeat(&ecs, (1, 0), 3)), (Point::new(13, 5), 3));
+
+        let mut waiting = Travel::repeat(&ecs, (0, 0), 2);
+        assert!(matches!(waiting.next_step(&ecs), TravelStep::Wait));
+        assert!(matches!(waiting.next_step(&ecs), TravelStep::Wait));
+        assert!(matches!(waiting.next_step(&ecs), TravelStep::Arrived));
+    }
+
+    #[test]
+    fn running_stops_at_the_end_of_a_corridor_or_where_it_opens_up() {
+        let mut ecs = fixture_world();
+        *ecs.fetch_mut::<Point>() = Point::new(22, 10);
+        assert_eq!(walk(&ecs, &mut Travel::run(&ecs, (1, 0))), (Point::new(30, 10), 8));
+
+        // back west, the walls either side give way at the mouth of the room
+        assert_eq!(walk(&ecs, &mut Travel::run(&ecs, (-1, 0))), (Point::new(21, 10), 9));
+
+        // an open door looks just like the corridor, but the run still stops short of it,
+        // and once through, the door behind doesn't hold the player up
+        let door = crate::spawner::door(&mut ecs, 25, 10, None);
+        ecs.write_storage::<Door>().get_mut(door).unwrap().open = true;
+        *ecs.fetch_mut::<Point>() = Point::new(22, 10);
+        assert_eq!(walk(&ecs, &mut Travel::run(&ecs, (1, 0))), (Point::new(24, 10), 2));
+        *ecs.fetch_mut::<Point>() = Point::new(25, 10);
+        assert_eq!(walk(&ecs, &mut Travel::run(&ecs, (1, 0))), (Point::new(30, 10), 5));
+
+        // one set in the wall alongside stops it too
+        let side_door = crate::spawner::door(&mut ecs, 28, 11, None);
+        *ecs.fetch_mut::<Point>() = Point::new(25, 10);
+        assert_eq!(walk(&ecs, &mut Travel::run(&ecs, (1, 0))), (Point::new(27, 10), 2));
+        ecs.delete_entity(door).unwrap();
+        ecs.delete_entity(side_door).unwrap();
+
+        // a side passage is a junction
+        {
+            let mut map = ecs.fetch_mut::<GameMap>();
+            let idx = map.xy_idx(26, 9);
+            map.tiles[idx] = TileType::Floor;
+            map.populate_blocked();
+        }
+        *ecs.fetch_mut::<Point>() = Point::new(22, 10);
+        assert_eq!(walk(&ecs, &mut Travel::run(&ecs, (1, 0))), (Point::new(25, 10), 3));
+    }
+
+    #[test]
+    fn rests_until_healed() {
+        let ecs = fixture_world();
+        assert!(matches!(Travel::rest(&ecs), Err(reason) if reason == "You can't rest with enemies in view."));
+
+        let orc = named(&ecs, "Orc");
+        ecs.write_storage::<Position>().get_mut(orc).unwrap().x = 26;
+        assert!(matches!(Travel::rest(&ecs), Err(reason) if reason == "You are already at full health."));
+
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<CombatStats>().get_mut(player).unwrap().hp = 29;
+        let mut rest = Travel::rest(&ecs).unwrap();
+        assert!(matches!(rest.next_step(&ecs), TravelStep::Wait));
+        ecs.write_storage::<CombatStats>().get_mut(player).unwrap().hp = 30;
+        assert!(matches!(rest.next_step(&ecs), TravelStep::Interrupted(reason) if reason == "You feel rested."));
+    }
+}
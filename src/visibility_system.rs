@@ -1,25 +1,57 @@
+use std::collections::HashSet;
 use specs::prelude::*;
-use crate::components::{Viewshed, Position, Player};
+use crate::components::{Viewshed, Position, Player, Hidden, Name};
 use crate::game_map::GameMap;
-use rltk::{field_of_view, Point};
+use crate::game_log::{GameLog, LogCategory};
+use crate::lighting_system::MIN_LIGHT;
+use crate::clock::GameClock;
+use crate::weather::WeatherState;
+use rltk::{field_of_view, Point, RandomNumberGenerator};
+
+/// What a d20 must reach to notice something hidden as it comes into view.
+const SPOT_DIFFICULTY: i32 = 17;
+
+/// The hidden things in view, and secret doors alongside, that the player has already
+/// had their chance to notice. Each gets another roll only after it has gone out of
+/// reach and come back.
+#[derive(Default)]
+pub struct SpotChecks {
+    pub entities: HashSet<Entity>,
+    pub secret_doors: HashSet<usize>
+}
 
 pub struct VisibilitySystem;
 
 impl<'a> System<'a> for VisibilitySystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = ( WriteExpect<'a, GameMap>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        WriteExpect<'a, GameLog>,
+                        ReadExpect<'a, GameClock>,
+                        ReadExpect<'a, WeatherState>,
+                        WriteExpect<'a, SpotChecks>,
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>,
                         WriteStorage<'a, Position>,
-                        ReadStorage<'a, Player>);
+                        ReadStorage<'a, Player>,
+                        WriteStorage<'a, Hidden>,
+                        ReadStorage<'a, Name> );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, entities, mut viewshed, pos, player) = data;
+        let (mut map, mut rng, mut log, clock, weather, mut checks, entities, mut viewshed, positions, player, mut hidden, names) = data;
 
-        for (ent, viewshed, pos) in (&entities, &mut viewshed, &pos).join() {
+        let mut spotted = Vec::new();
+        for (ent, viewshed, pos) in (&entities, &mut viewshed, &positions).join() {
             if viewshed.dirty {
                 viewshed.dirty = false;
                 viewshed.visible_tiles.clear();
-                viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
+                // out in the open, everyone sees less as the light fails or the dust blows
+                let range = if map.outdoors {
+                    weather.weather.sight_range(clock.sight_range(viewshed.range))
+                } else {
+                    viewshed.range
+                };
+                viewshed.visible_tiles = field_of_view(Point::new(pos.x, pos.y), range, &*map);
                 viewshed.visible_tiles.retain(|p| p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1);
 
                 let p: Option<&Player> = player.get(ent);
@@ -28,13 +60,123 @@ impl<'a> System<'a> for VisibilitySystem {
                         *t = false;
                     }
 
+                    // only what's lit can be made out, besides what's close enough to touch
                     for vis in viewshed.visible_tiles.iter() {
                         let idx = map.xy_idx(vis.x, vis.y);
-                        map.revealed_tiles[idx] = true;
-                        map.visible_tiles[idx] = true;
+                        let within_reach = (vis.x - pos.x).abs() <= 1 && (vis.y - pos.y).abs() <= 1;
+                        if within_reach || map.light[idx] >= MIN_LIGHT {
+                            map.revealed_tiles[idx] = true;
+                            map.visible_tiles[idx] = true;
+                        }
+                    }
+
+                    // one chance to notice each hidden thing as it comes into view
+                    let mut in_view = HashSet::new();
+                    for (hidden_entity, _hidden, hidden_pos) in (&entities, &hidden, &positions).join() {
+                        if !map.visible_tiles[map.xy_idx(hidden_pos.x, hidden_pos.y)] {
+                            continue;
+                        }
+                        if !checks.entities.contains(&hidden_entity) && rng.roll_dice(1, 20) >= SPOT_DIFFICULTY {
+                            spotted.push(hidden_entity);
+                        }
+                        in_view.insert(hidden_entity);
                     }
+                    checks.entities = in_view;
+
+                    // and to notice a secret passage in the walls alongside
+                    let mut alongside = HashSet::new();
+                    for y in pos.y - 1 ..= pos.y + 1 {
+                        for x in pos.x - 1 ..= pos.x + 1 {
+                            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                                continue;
+                            }
+                            let idx = map.xy_idx(x, y);
+                            if !map.secret_doors[idx] {
+                                continue;
+                            }
+                            if !checks.secret_doors.contains(&idx) && rng.roll_dice(1, 20) >= SPOT_DIFFICULTY {
+                                map.reveal_secret_door(idx);
+                                log.log(LogCategory::General, "You notice a secret passage.");
+                                viewshed.dirty = true;
+                            } else {
+                                alongside.insert(idx);
+                            }
+                        }
+                    }
+                    checks.secret_doors = alongside;
                 }
             }
         }
+
+        for entity in spotted {
+            hidden.remove(entity);
+            let name = names.get(entity).map_or("something", |name| name.name.as_str());
+            log.log(LogCategory::General, format!("You spot a {}.", name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spawner;
+    use crate::snapshot::fixture_world;
+
+    fn hidden_left(ecs: &World) -> usize {
+        ecs.read_storage::<Hidden>().count()
+    }
+
+    fn look_from(ecs: &World, x: i32, y: i32) {
+        let player = *ecs.fetch::<Entity>();
+        *ecs.write_storage::<Position>().get_mut(player).unwrap() = Position { x, y };
+        ecs.write_storage::<Viewshed>().get_mut(player).unwrap().dirty = true;
+        VisibilitySystem.run_now(ecs);
+    }
+
+    #[test]
+    fn hidden_things_get_one_roll_each_time_they_come_into_view() {
+        let mut ecs = fixture_world();
+        for x in 5..=16 {
+            spawner::random_trap(&mut ecs, x, 12);
+        }
+
+        look_from(&ecs, 10, 10);
+        let missed = hidden_left(&ecs);
+        assert!(missed > 0 && missed < 12, "{} of 12 traps still hidden", missed);
+        for _ in 0..100 {
+            look_from(&ecs, 10, 10);
+        }
+        assert_eq!(hidden_left(&ecs), missed, "standing still shouldn't keep rolling");
+
+        // walking out of the room and back in is a fresh look
+        for _ in 0..20 {
+            look_from(&ecs, 30, 10);
+            look_from(&ecs, 10, 10);
+        }
+        assert!(hidden_left(&ecs) < missed);
+    }
+
+    #[test]
+    fn a_secret_passage_alongside_gets_one_roll_until_the_player_steps_away() {
+        let ecs = fixture_world();
+        let secrets: Vec<usize> = (9..=11).map(|x| ecs.fetch::<GameMap>().xy_idx(x, 4)).collect();
+        for idx in secrets.iter() {
+            ecs.fetch_mut::<GameMap>().secret_doors[*idx] = true;
+        }
+        let still_secret = || secrets.iter().filter(|idx| ecs.fetch::<GameMap>().secret_doors[**idx]).count();
+
+        look_from(&ecs, 10, 5);
+        let missed = still_secret();
+        assert!(missed > 0, "all three found at a glance");
+        for _ in 0..100 {
+            look_from(&ecs, 10, 5);
+        }
+        assert_eq!(still_secret(), missed, "standing still shouldn't keep rolling");
+
+        for _ in 0..20 {
+            look_from(&ecs, 10, 10);
+            look_from(&ecs, 10, 5);
+        }
+        assert!(still_secret() < missed);
     }
-}
\ No newline at end of file
+}
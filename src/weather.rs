@@ -0,0 +1,204 @@
+use specs::prelude::*;
+use rltk::{RGB, RandomNumberGenerator};
+use crate::game_map::{GameMap, TileType};
+use crate::game_log::{GameLog, LogBuilder, LogCategory};
+use crate::components::Viewshed;
+use crate::frontend::Frontend;
+use crate::clock::GameClock;
+use super::RunState;
+
+/// Turns a monster keeps following the player's tracks after losing sight of them.
+pub const TRACKING_TURNS: i32 = 10;
+/// The same in the rain.
+const RAIN_TRACKING_TURNS: i32 = 3;
+
+/// The sky over outdoor maps. Nothing in the game shoots or makes noise yet, so
+/// the weather only changes how far everyone sees and how long tracks last.
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Weather {
+    Clear,
+    DustStorm,
+    Rain
+}
+
+impl Weather {
+    /// How far something that sees `range` tiles in clear air can see through this.
+    pub fn sight_range(&self, range: i32) -> i32 {
+        match self {
+            Weather::DustStorm => i32::max(range / 2, 1),
+            Weather::Clear | Weather::Rain => range
+        }
+    }
+
+    /// How many turns a monster can follow tracks on open ground before they give out.
+    /// Rain washes them away quickly.
+    pub fn tracking_turns(&self) -> i32 {
+        match self {
+            Weather::Rain => RAIN_TRACKING_TURNS,
+            Weather::Clear | Weather::DustStorm => TRACKING_TURNS
+        }
+    }
+}
+
+/// The sky over the surface, and how many more turns it stays that way.
+pub struct WeatherState {
+    pub weather: Weather,
+    pub turns_left: i32
+}
+
+impl Default for WeatherState {
+    fn default() -> WeatherState {
+        WeatherState { weather: Weather::Clear, turns_left: 100 }
+    }
+}
+
+/// Changes the weather now and then while the player is out in the open, and tells
+/// them about it. A dust storm cuts everyone's sight, so viewsheds are redone; rain
+/// shortens how long `MonsterAI` can follow the player's tracks.
+pub struct WeatherSystem;
+
+impl<'a> System<'a> for WeatherSystem {
+    type SystemData = ( WriteExpect<'a, WeatherState>,
+                        ReadExpect<'a, GameMap>,
+                        ReadExpect<'a, RunState>,
+                        WriteExpect<'a, RandomNumberGenerator>,
+                        WriteExpect<'a, GameLog>,
+                        WriteStorage<'a, Viewshed> );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut state, map, run_state, mut rng, mut log, mut viewsheds) = data;
+
+        if *run_state != RunState::PlayerTurn || !map.outdoors {
+            return;
+        }
+        state.turns_left -= 1;
+        if state.turns_left > 0 {
+            return;
+        }
+
+        let before = state.weather;
+        state.weather = match rng.roll_dice(1, 10) {
+            1 | 2 => Weather::DustStorm,
+            3 | 4 => Weather::Rain,
+            _ => Weather::Clear
+        };
+        state.turns_left = rng.roll_dice(1, 100) + 50;
+        if state.weather == before {
+            return;
+        }
+
+        let news = match (before, state.weather) {
+            (_, Weather::DustStorm) => "A dust storm blows in off the desert.",
+            (_, Weather::Rain) => "It starts to rain.",
+            (Weather::DustStorm, _) => "The dust settles.",
+            _ => "The rain lets up."
+        };
+        LogBuilder::new(LogCategory::General).text(news).major().log(&mut log);
+        for viewshed in (&mut viewsheds).join() {
+            viewshed.dirty = true;
+        }
+    }
+}
+
+/// A cheap, repeatable scatter so particles drift from turn to turn without any state.
+fn scatter(a: i32, b: i32) -> u32 {
+    (a as u32).wrapping_mul(73_856_093) ^ (b as u32).wrapping_mul(19_349_663)
+}
+
+/// Draws blowing dust or falling rain over the open ground in view. Called between
+/// the map and the entities, so nothing standing there gets hidden.
+pub fn draw_particles(map: &GameMap, weather: &WeatherState, clock: &GameClock, ctx: &mut dyn Frontend) {
+    if !map.outdoors || weather.weather == Weather::Clear {
+        return;
+    }
+    let turn = clock.turn as i32;
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let idx = map.xy_idx(x, y);
+            if !map.visible_tiles[idx] || !matches!(map.tiles[idx], TileType::Sand | TileType::Road) {
+                continue;
+            }
+            // dust is blown along from the west, rain comes down from above
+            let (glyph, fg, hit) = match weather.weather {
+                Weather::DustStorm => ('∙', RGB::named(rltk::BURLYWOOD), scatter(x - turn, y).is_multiple_of(5)),
+                _ => ('\'', RGB::named(rltk::STEELBLUE), scatter(x, y - turn).is_multiple_of(7))
+            };
+            if hit {
+                ctx.set(x, y, fg, RGB::named(rltk::BLACK), rltk::to_cp437(glyph));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::visibility_system::VisibilitySystem;
+    use crate::frame_buffer::FrameBuffer;
+    use crate::snapshot::fixture_world;
+
+    fn outdoor_world() -> World {
+        let ecs = fixture_world();
+        {
+            let mut map = ecs.fetch_mut::<GameMap>();
+            map.outdoors = true;
+            for tile in map.tiles.iter_mut().filter(|tile| **tile == TileType::Floor) {
+                *tile = TileType::Sand;
+            }
+        }
+        ecs
+    }
+
+    fn player_sight(ecs: &World) -> usize {
+        VisibilitySystem.run_now(ecs);
+        ecs.read_storage::<Viewshed>().get(*ecs.fetch::<Entity>()).unwrap().visible_tiles.len()
+    }
+
+    #[test]
+    fn the_weather_turns_and_the_news_is_logged() {
+        let mut ecs = outdoor_world();
+        ecs.insert(RunState::PlayerTurn);
+        let mut changes = 0;
+        for _ in 0..2000 {
+            let before = ecs.fetch::<WeatherState>().weather;
+            WeatherSystem.run_now(&ecs);
+            if ecs.fetch::<WeatherState>().weather != before {
+                changes += 1;
+            }
+        }
+        assert!(changes > 2);
+        {
+            let log = ecs.fetch::<GameLog>();
+            let news: Vec<&str> = log.entries.iter().map(|entry| entry.fragments[0].text.as_str()).take(changes).collect();
+            assert!(news.iter().all(|text| [
+                "A dust storm blows in off the desert.", "It starts to rain.", "The dust settles.", "The rain lets up."
+            ].contains(text)), "{:?}", news);
+        }
+
+        // indoors nothing changes
+        ecs.fetch_mut::<GameMap>().outdoors = false;
+        ecs.insert(WeatherState { weather: Weather::Rain, turns_left: 1 });
+        WeatherSystem.run_now(&ecs);
+        assert_eq!(ecs.fetch::<WeatherState>().turns_left, 1);
+    }
+
+    #[test]
+    fn a_dust_storm_cuts_sight_and_blows_across_the_open_ground() {
+        let mut ecs = outdoor_world();
+        let clear = player_sight(&ecs);
+
+        ecs.insert(WeatherState { weather: Weather::DustStorm, turns_left: 100 });
+        ecs.write_storage::<Viewshed>().get_mut(*ecs.fetch::<Entity>()).unwrap().dirty = true;
+        let stormy = player_sight(&ecs);
+        assert!(stormy < clear / 2, "{} tiles in a storm, {} in clear air", stormy, clear);
+
+        let mut frame = FrameBuffer::new(80, 50);
+        {
+            let map = ecs.fetch::<GameMap>();
+            draw_particles(&map, &ecs.fetch::<WeatherState>(), &ecs.fetch::<GameClock>(), &mut frame);
+            let dust: Vec<usize> = (0..map.tiles.len()).filter(|idx| frame.get(*idx as i32 % 80, *idx as i32 / 80).glyph == rltk::to_cp437('∙')).collect();
+            assert!(!dust.is_empty());
+            assert!(dust.iter().all(|idx| map.visible_tiles[*idx] && map.tiles[*idx] == TileType::Sand));
+        }
+    }
+}